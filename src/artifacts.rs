@@ -0,0 +1,882 @@
+// Tape and vinyl artifacts, the imperfections of the medium rather than of
+// the magnetization.
+
+use rand_xoshiro::Xoshiro256Plus;
+use rand_xoshiro::rand_core::RngCore;
+
+use std::f64::consts::PI;
+
+use crate::delay::{DelayLine, Interpolation};
+use crate::envelope::Envelope;
+use crate::filters::{Biquad, OnePole};
+
+// decay time of a splice click and the low-pass giving it some thump
+const SPLICE_DECAY: f64 = 0.0015;
+const SPLICE_CUTOFF: f64 = 2500.0;
+// vinyl: rotation rate at 33 1/3 rpm in Hz, cutoff of the two rumble poles in
+// Hz and the rumble level relative to the click level, the largest delay
+// swing of the wow from an off-center hole in seconds (about 0.1% pitch)
+const ROTATION: f64 = 100.0 / 3.0 / 60.0;
+const RUMBLE_CUTOFF: f64 = 25.0;
+const RUMBLE_LEVEL: f64 = 0.25;
+const WOW_DEPTH: f64 = 0.0003;
+// wow and flutter: largest delay swing of the wow in seconds (about 0.5% pitch
+// at 1 Hz) and of the flutter (about 0.1% at 10 Hz), and the cutoff in Hz and
+// share of the random scrape in the flutter
+const WOW_SWING: f64 = 0.0008;
+const FLUTTER_SWING: f64 = 0.000016;
+const SCRAPE_CUTOFF: f64 = 100.0;
+const SCRAPE_SHARE: f64 = 0.5;
+// capstan instability: largest delay swing of the wander in seconds, the
+// standard deviation of the random walk after a second, in its range of -1 to
+// 1, and the cutoff in Hz of the low-pass smoothing it
+const INSTABILITY_SWING: f64 = 0.0004;
+const WALK_SPEED: f64 = 1.0;
+const WANDER_CUTOFF: f64 = 4.0;
+// stereo flutter: phase of the right channel's wow and flutter cycles ahead
+// of the left, a quarter cycle leaves the two uncorrelated
+const STEREO_PHASE: f64 = 0.25;
+// time constant in seconds of the glide from the old delay into the new cycle
+// when the modulation is re-synced
+const RESYNC_TIME: f64 = 0.02;
+// hiss: corner and boost in dB of the shelf giving it the hiss of tape, the
+// top of its band and the crossover below which it can be shared by both
+// channels, in Hz. Asperity noise: its level relative to the hiss at full
+// scale, and the envelope times in seconds it follows the signal with
+const HISS_SHELF: f64 = 3000.0;
+const HISS_BOOST: f64 = 6.0;
+const HISS_TOP: f64 = 15000.0;
+const HISS_XOVER: f64 = 500.0;
+const ASPERITY_GAIN: f64 = 10.0;
+const ASPERITY_ATTACK: f64 = 0.001;
+const ASPERITY_RELEASE: f64 = 0.02;
+// rhythmic noise: largest swing of the noise level around unity, the noise
+// is loudest on the beat
+const RHYTHM_SWING: f64 = 0.5;
+// dropouts: shortest and longest dip in seconds, the level lost at the bottom
+// of the deepest dip in dB, the cutoff of the low-pass the highs fade into,
+// and the smallest share of the depth an event gets
+const DROPOUT_MIN: f64 = 0.02;
+const DROPOUT_MAX: f64 = 0.2;
+const DROPOUT_DB: f64 = 24.0;
+const DROPOUT_CUTOFF: f64 = 3000.0;
+const DROPOUT_SPREAD: f64 = 0.3;
+
+/// uniform random number in [0, 1)
+pub fn uniform(rng: &mut Xoshiro256Plus) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// splice clicks, short thumps at random (poisson distributed) times
+pub struct Splice {
+    env: f64,       // amplitude of the current click
+    decay: f64,     // per sample decay of the click
+    lp: OnePole,
+    rate: f64,      // intersample period
+}
+
+impl Splice {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        let mut s = Splice { env: 0.0, decay: 0.0, lp: OnePole::new(SPLICE_CUTOFF, rate), rate };
+        s.set_rate(rate);
+        s
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.decay = (-rate / SPLICE_DECAY).exp();
+        self.lp.set_cutoff(SPLICE_CUTOFF, rate);
+    }
+
+    /// generate one sample of click signal
+    /// + per_sec:  average number of clicks per second, 0 never clicks
+    /// + level:    peak level of a click, linear
+    /// + rng:      random number generator
+    pub fn step(&mut self, per_sec: f64, level: f64, rng: &mut Xoshiro256Plus) -> f64 {
+        if per_sec > 0.0 && uniform(rng) < per_sec * self.rate {
+            self.trigger(level, rng);
+        }
+        self.tick()
+    }
+
+    /// start a click now
+    /// + level:    peak level of a click, linear
+    /// + rng:      random number generator
+    pub fn trigger(&mut self, level: f64, rng: &mut Xoshiro256Plus) {
+        // random polarity and strength, so no two clicks sound alike
+        let strength = 0.5 + 0.5 * uniform(rng);
+        let sign = if uniform(rng) < 0.5 { -1.0 } else { 1.0 };
+        self.env = sign * strength * level;
+    }
+
+    /// one sample of the current click
+    fn tick(&mut self) -> f64 {
+        let y = self.lp.step(self.env);
+        self.env *= self.decay;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.env = 0.0;
+        self.lp.reset();
+    }
+}
+
+/// vinyl artifacts, all locked to the rotation of the record: a scratch that
+/// clicks once per revolution, the wow of an off-center hole and the rumble of
+/// the turntable
+pub struct Vinyl {
+    phase: f64,         // rotation phase, 0 - 1
+    scratch: Splice,
+    rumble: [OnePole; 2],
+    rumble_gain: f64,   // brings the filtered noise back to about unity
+    wow: [DelayLine; 2],
+    depth: f64,         // wow swing in samples
+    center: usize,      // delay the wow is centered on, in samples
+    swing: f64,         // last swing of the wow, in samples
+    offset: f64,        // what is left of the swing before a resync
+    glide: f64,         // per sample decay of that
+    rate: f64,          // intersample period
+}
+
+impl Vinyl {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        let mut v = Vinyl {
+            phase: 0.0,
+            scratch: Splice::new(rate),
+            rumble: [OnePole::new(RUMBLE_CUTOFF, rate), OnePole::new(RUMBLE_CUTOFF, rate)],
+            rumble_gain: 1.0,
+            wow: [DelayLine::new(1), DelayLine::new(1)],
+            depth: 0.0,
+            center: 0,
+            swing: 0.0,
+            offset: 0.0,
+            glide: 0.0,
+            rate,
+        };
+        v.set_rate(rate);
+        v
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.scratch.set_rate(rate);
+        for lp in self.rumble.iter_mut() {
+            lp.set_cutoff(RUMBLE_CUTOFF, rate);
+        }
+        // variance of white noise through a one-pole is a / (2 - a)
+        let a = 1.0 - (-2.0 * PI * RUMBLE_CUTOFF * rate).exp();
+        self.rumble_gain = ((2.0 - a) / a).sqrt();
+        self.depth = WOW_DEPTH / rate;
+        self.center = self.depth.ceil() as usize + 1;
+        self.wow = [DelayLine::new(2 * self.center), DelayLine::new(2 * self.center)];
+        self.glide = (-rate / RESYNC_TIME).exp();
+    }
+
+    /// delay the wow is centered on, in samples, which is the latency
+    pub fn latency(&self) -> usize {
+        self.center
+    }
+
+    /// generate one sample of scratch and rumble, and advance the rotation
+    /// + level:    peak level of the scratch, linear, the rumble follows it
+    /// + rng:      random number generator
+    pub fn step(&mut self, level: f64, rng: &mut Xoshiro256Plus) -> f64 {
+        self.phase += ROTATION * self.rate;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.scratch.trigger(level, rng);
+        }
+        let noise = 2.0 * uniform(rng) - 1.0;
+        let r = self.rumble[0].step(noise * self.rumble_gain);
+        let r = self.rumble[1].step(r);
+        self.scratch.tick() + r * level * RUMBLE_LEVEL
+    }
+
+    /// pitch wow of a stereo sample, a delay swinging once per revolution
+    /// around the latency
+    pub fn wow(&mut self, l: f64, r: f64) -> (f64, f64) {
+        self.swing = self.depth * (2.0 * PI * self.phase).sin();
+        self.offset *= self.glide;
+        // the glide after a resync can briefly reach past the line
+        let delay = (self.center as f64 + self.swing + self.offset).clamp(1.0, (2 * self.center - 1) as f64);
+        self.wow[0].push(l);
+        self.wow[1].push(r);
+        (
+            self.wow[0].read_frac(delay, Interpolation::Hermite),
+            self.wow[1].read_frac(delay, Interpolation::Hermite),
+        )
+    }
+
+    /// start the rotation over, the wow glides from its delay into the new
+    /// revolution so it doesn't jump
+    pub fn resync(&mut self) {
+        self.offset += self.swing;
+        self.swing = 0.0;
+        self.phase = 0.0;
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.swing = 0.0;
+        self.offset = 0.0;
+        self.scratch.reset();
+        for lp in self.rumble.iter_mut() {
+            lp.reset();
+        }
+        for line in self.wow.iter_mut() {
+            line.reset();
+        }
+    }
+}
+
+/// wow and flutter of the tape transport, a delay swinging slowly with the
+/// capstan and reels (wow) and fast with the rollers (flutter), on top of a
+/// random scrape of the tape across the heads and the irregular wander of the
+/// capstan speed. The right channel can move on its own, with its cycles a
+/// quarter ahead and a scrape and wander of its own.
+pub struct Transport {
+    wow_phase: f64,     // 0 - 1
+    flutter_phase: f64, // 0 - 1
+    scrape: [OnePole; 2],
+    scrape_gain: f64,   // brings the filtered noise back to about unity
+    walk: [f64; 2],     // bounded random walk, -1 - 1
+    wander: [OnePole; 2],
+    lines: [DelayLine; 2],
+    center: usize,      // delay the swing is centered on, in samples
+    swing: [f64; 2],    // last swing of the periodic part, in samples
+    offset: [f64; 2],   // what is left of the swing before a resync
+    glide: f64,         // per sample decay of that
+    rate: f64,          // intersample period
+}
+
+impl Transport {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        let mut t = Transport {
+            wow_phase: 0.0,
+            flutter_phase: 0.0,
+            scrape: [OnePole::new(SCRAPE_CUTOFF, rate), OnePole::new(SCRAPE_CUTOFF, rate)],
+            scrape_gain: 1.0,
+            walk: [0.0; 2],
+            wander: [OnePole::new(WANDER_CUTOFF, rate), OnePole::new(WANDER_CUTOFF, rate)],
+            lines: [DelayLine::new(1), DelayLine::new(1)],
+            center: 0,
+            swing: [0.0; 2],
+            offset: [0.0; 2],
+            glide: 0.0,
+            rate,
+        };
+        t.set_rate(rate);
+        t
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        for (scrape, wander) in self.scrape.iter_mut().zip(self.wander.iter_mut()) {
+            scrape.set_cutoff(SCRAPE_CUTOFF, rate);
+            wander.set_cutoff(WANDER_CUTOFF, rate);
+        }
+        let a = 1.0 - (-2.0 * PI * SCRAPE_CUTOFF * rate).exp();
+        self.scrape_gain = ((2.0 - a) / a).sqrt();
+        self.center = ((WOW_SWING + FLUTTER_SWING + INSTABILITY_SWING) / rate).ceil() as usize + 1;
+        self.lines = [DelayLine::new(2 * self.center), DelayLine::new(2 * self.center)];
+        self.glide = (-rate / RESYNC_TIME).exp();
+    }
+
+    /// delay the swing is centered on, in samples, which is the latency
+    pub fn latency(&self) -> usize {
+        self.center
+    }
+
+    /// wow and flutter of a stereo sample. Without stereo flutter both
+    /// channels move together like they do on one tape, with it the right
+    /// channel fades into a modulation of its own, uncorrelated at full depth.
+    /// + x:            left and right input
+    /// + wow:          depth 0 - 1 and rate in Hz of the wow
+    /// + flutter:      depth 0 - 1 and rate in Hz of the flutter
+    /// + instability:  depth of the capstan wander, 0 - 1
+    /// + stereo:       how much the right channel moves on its own, 0 - 1
+    /// + rng:          random number generator for the scrape and the wander
+    pub fn step(&mut self, x: (f64, f64), wow: (f64, f64), flutter: (f64, f64),
+                instability: f64, stereo: f64, rng: &mut Xoshiro256Plus) -> (f64, f64) {
+        self.wow_phase = (self.wow_phase + wow.1 * self.rate).fract();
+        self.flutter_phase = (self.flutter_phase + flutter.1 * self.rate).fract();
+
+        // the periodic swing of a channel, its cycles ahead by a phase, and
+        // the random part from its scrape and wander, in samples
+        let periodic = |t: &Self, phase: f64| {
+            let w = wow.0 * WOW_SWING * (2.0 * PI * (t.wow_phase + phase)).sin();
+            let f = flutter.0 * FLUTTER_SWING * (1.0 - SCRAPE_SHARE) * (2.0 * PI * (t.flutter_phase + phase)).sin();
+            (w + f) / t.rate
+        };
+        let random = |t: &mut Self, ch: usize, rng: &mut Xoshiro256Plus| {
+            let noise = 2.0 * uniform(rng) - 1.0;
+            let scrape = t.scrape[ch].step(noise * t.scrape_gain).clamp(-1.0, 1.0);
+            let wander = t.wander_step(ch, instability > 0.0, rng);
+            (flutter.0 * FLUTTER_SWING * SCRAPE_SHARE * scrape + instability * INSTABILITY_SWING * wander) / t.rate
+        };
+        self.swing[0] = periodic(self, 0.0);
+        let left = random(self, 0, rng);
+        // the right channel only draws from the rng while it moves on its own,
+        // so the random sequence of the other artifacts is what it was
+        // without it. It fades linearly, which keeps it within the line.
+        let (swing, right) = if stereo > 0.0 {
+            (periodic(self, STEREO_PHASE), random(self, 1, rng))
+        } else {
+            (self.swing[0], left)
+        };
+        self.swing[1] = self.swing[0] + stereo * (swing - self.swing[0]);
+        let right = left + stereo * (right - left);
+
+        let mut y = [0.0; 2];
+        for (ch, &(x, random)) in [(x.0, left), (x.1, right)].iter().enumerate() {
+            self.offset[ch] *= self.glide;
+            // the glide after a resync can briefly reach past the line
+            let delay = (self.center as f64 + self.swing[ch] + random + self.offset[ch])
+                .clamp(1.0, (2 * self.center - 1) as f64);
+            self.lines[ch].push(x);
+            y[ch] = self.lines[ch].read_frac(delay, Interpolation::Hermite);
+        }
+        (y[0], y[1])
+    }
+
+    /// the capstan wander of a channel, a random walk reflected at -1 and 1
+    /// so it never leaves them, smoothed into a slow drift. It only moves
+    /// while on, so the random sequence of the other artifacts is what it was
+    /// without it.
+    fn wander_step(&mut self, ch: usize, on: bool, rng: &mut Xoshiro256Plus) -> f64 {
+        if on {
+            // uniform steps with the variance of WALK_SPEED per second
+            let step = WALK_SPEED * (3.0 * self.rate).sqrt() * (2.0 * uniform(rng) - 1.0);
+            let walk = &mut self.walk[ch];
+            *walk += step;
+            if *walk > 1.0 {
+                *walk = 2.0 - *walk;
+            } else if *walk < -1.0 {
+                *walk = -2.0 - *walk;
+            }
+        }
+        self.wander[ch].step(self.walk[ch])
+    }
+
+    /// start the wow and flutter cycles over, the delay glides from where it
+    /// is into the new cycles so it doesn't jump
+    pub fn resync(&mut self) {
+        for (offset, swing) in self.offset.iter_mut().zip(self.swing.iter_mut()) {
+            *offset += *swing;
+            *swing = 0.0;
+        }
+        self.wow_phase = 0.0;
+        self.flutter_phase = 0.0;
+    }
+
+    pub fn reset(&mut self) {
+        self.wow_phase = 0.0;
+        self.flutter_phase = 0.0;
+        self.swing = [0.0; 2];
+        self.offset = [0.0; 2];
+        self.walk = [0.0; 2];
+        for (scrape, wander) in self.scrape.iter_mut().zip(self.wander.iter_mut()) {
+            scrape.reset();
+            wander.reset();
+        }
+        for line in self.lines.iter_mut() {
+            line.reset();
+        }
+    }
+}
+
+/// tape noise, a steady bias hiss and asperity noise riding on the signal,
+/// from the roughness of the tape surface. The channels are uncorrelated, or
+/// share their lows like on real tape, where the highs are what differs.
+pub struct Hiss {
+    shelf: [Biquad; 2],
+    top: [Biquad; 2],
+    lows: [Biquad; 2],
+    env: [Envelope; 2],
+    rate: f64,      // intersample period
+}
+
+impl Hiss {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        let mut h = Hiss {
+            shelf: [Biquad::new(), Biquad::new()],
+            top: [Biquad::new(), Biquad::new()],
+            lows: [Biquad::new(), Biquad::new()],
+            env: [
+                Envelope::new(ASPERITY_ATTACK, ASPERITY_RELEASE, rate),
+                Envelope::new(ASPERITY_ATTACK, ASPERITY_RELEASE, rate),
+            ],
+            rate,
+        };
+        h.set_rate(rate);
+        h
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        let top = HISS_TOP.min(0.45 / rate);
+        for (shelf, lp) in self.shelf.iter_mut().zip(self.top.iter_mut()) {
+            shelf.set_high_shelf(HISS_SHELF, 0.707, HISS_BOOST, rate);
+            lp.set_lowpass(top, 0.707, rate);
+        }
+        for lows in self.lows.iter_mut() {
+            lows.set_lowpass(HISS_XOVER, 0.707, rate);
+        }
+        for env in self.env.iter_mut() {
+            env.set_times(ASPERITY_ATTACK, ASPERITY_RELEASE, rate);
+        }
+    }
+
+    /// generate one stereo sample of noise
+    /// + level:        hiss level, linear, the asperity noise follows it
+    /// + x:            left and right signal on the tape
+    /// + mono_lows:    how far the band below HISS_XOVER is shared by both
+    ///   channels, 0 - 1
+    /// + rng:          random number generator
+    pub fn step(&mut self, level: f64, x: (f64, f64), mono_lows: f64,
+                rng: &mut Xoshiro256Plus) -> (f64, f64) {
+        let mut n = [0.0; 2];
+        let mut lows = [0.0; 2];
+        for (ch, (n, lows)) in n.iter_mut().zip(lows.iter_mut()).enumerate() {
+            // uniform noise scaled to unity RMS
+            let noise = 3f64.sqrt() * (2.0 * uniform(rng) - 1.0);
+            *n = self.top[ch].step(self.shelf[ch].step(noise));
+            *lows = self.lows[ch].step(*n);
+        }
+        // the sum of two uncorrelated bands, at the power of one
+        let mono = (lows[0] + lows[1]) * std::f64::consts::FRAC_1_SQRT_2;
+        let mut out = [0.0; 2];
+        for (ch, (y, x)) in out.iter_mut().zip([x.0, x.1]).enumerate() {
+            let env = self.env[ch].step(x);
+            let n = n[ch] + mono_lows * (mono - lows[ch]);
+            *y = level * n * (1.0 + ASPERITY_GAIN * env);
+        }
+        (out[0], out[1])
+    }
+
+    pub fn reset(&mut self) {
+        for f in self.shelf.iter_mut().chain(self.top.iter_mut()).chain(self.lows.iter_mut()) {
+            f.reset();
+        }
+        for env in self.env.iter_mut() {
+            env.reset();
+        }
+    }
+}
+
+/// level pulse locked to the host tempo, the noise swelling on every beat
+/// and dipping between them. Without a tempo it holds at unity.
+pub struct Pulse {
+    pos: f64,   // position in beats
+    step: f64,  // beats per sample, 0 without a tempo
+}
+
+impl Pulse {
+    pub fn new() -> Self {
+        Pulse { pos: 0.0, step: 0.0 }
+    }
+
+    /// follow the host, at the start of a block
+    /// + beat:     position in beats and tempo in bpm, None without a tempo
+    /// + rate:     intersample period
+    pub fn sync(&mut self, beat: Option<(f64, f64)>, rate: f64) {
+        match beat {
+            Some((pos, bpm)) if bpm > 0.0 => {
+                self.pos = pos;
+                self.step = bpm / 60.0 * rate;
+            }
+            _ => self.step = 0.0,
+        }
+    }
+
+    /// gain of the noise for one sample
+    /// + depth:    0 - 1
+    pub fn step(&mut self, depth: f64) -> f64 {
+        if self.step <= 0.0 {
+            return 1.0;
+        }
+        let gain = 1.0 + depth * RHYTHM_SWING * (2.0 * PI * self.pos.fract()).cos();
+        self.pos += self.step;
+        gain
+    }
+}
+
+impl Default for Pulse {
+    fn default() -> Self {
+        Pulse::new()
+    }
+}
+
+/// dropouts, brief dips in level and highs where the tape lost contact with
+/// the head, at random (poisson distributed) times, one channel
+pub struct Dropout {
+    remaining: usize,   // samples left in the current dip, 0 when there is none
+    len: usize,         // length of the current dip in samples
+    depth: f64,         // depth of the current dip, 0 - 1
+    lp: OnePole,
+    rate: f64,          // intersample period
+}
+
+impl Dropout {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        Dropout { remaining: 0, len: 1, depth: 0.0, lp: OnePole::new(DROPOUT_CUTOFF, rate), rate }
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.lp.set_cutoff(DROPOUT_CUTOFF, rate);
+    }
+
+    /// process one sample, passes it through untouched outside of the dips
+    /// + x:        input
+    /// + per_sec:  average number of dips per second, 0 never dips
+    /// + depth:    depth of the deepest dips, 0 - 1
+    /// + rng:      random number generator
+    pub fn step(&mut self, x: f64, per_sec: f64, depth: f64, rng: &mut Xoshiro256Plus) -> f64 {
+        let lp = self.lp.step(x);
+        if self.remaining == 0 {
+            if per_sec <= 0.0 || uniform(rng) >= per_sec * self.rate {
+                return x;
+            }
+            // random length and depth, so no two dips sound alike
+            let secs = DROPOUT_MIN + (DROPOUT_MAX - DROPOUT_MIN) * uniform(rng);
+            self.len = ((secs / self.rate) as usize).max(1);
+            self.remaining = self.len;
+            self.depth = depth * (DROPOUT_SPREAD + (1.0 - DROPOUT_SPREAD) * uniform(rng));
+        }
+        // raised cosine in and out of the dip
+        let p = 1.0 - self.remaining as f64 / self.len as f64;
+        let dip = self.depth * (0.5 - 0.5 * (2.0 * PI * p).cos());
+        self.remaining -= 1;
+        let gain = 10f64.powf(-DROPOUT_DB * dip / 20.0);
+        gain * (x + dip * (lp - x))
+    }
+
+    pub fn reset(&mut self) {
+        self.remaining = 0;
+        self.lp.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_xoshiro::rand_core::SeedableRng;
+
+    const RATE: f64 = 1.0 / 44100.0;
+
+    fn rng() -> Xoshiro256Plus {
+        Xoshiro256Plus::seed_from_u64(1)
+    }
+
+    #[test]
+    fn splice_clicks_come_at_their_rate() {
+        let mut rng = rng();
+        let mut s = Splice::new(RATE);
+        assert!((0..441000).all(|_| s.step(0.0, 1.0, &mut rng) == 0.0));
+
+        // a click starts wherever the envelope jumps instead of decaying
+        let mut clicks = 0;
+        for _ in 0..4410000 {
+            let before = s.env.abs() * s.decay;
+            s.step(2.0, 1.0, &mut rng);
+            if s.env.abs() > before + 1e-9 {
+                clicks += 1;
+            }
+        }
+        // 200 expected in 100 s, within about 3 standard deviations
+        assert!((160..=240).contains(&clicks), "{} clicks", clicks);
+    }
+
+    #[test]
+    fn vinyl_scratches_once_per_revolution() {
+        let mut rng = rng();
+        let mut v = Vinyl::new(RATE);
+        let mut clicks = Vec::new();
+        for n in 0..(10.0 / RATE) as usize {
+            let before = v.scratch.env.abs() * v.scratch.decay;
+            v.step(1.0, &mut rng);
+            if v.scratch.env.abs() > before + 1e-9 {
+                clicks.push(n);
+            }
+        }
+        assert_eq!(clicks.len(), 5);
+        let period = 1.0 / ROTATION / RATE;
+        assert!(clicks.windows(2).all(|w| ((w[1] - w[0]) as f64 - period).abs() <= 1.0));
+    }
+
+    #[test]
+    fn vinyl_rumbles_only_in_the_lows() {
+        let mut rng = rng();
+        let mut v = Vinyl::new(RATE);
+        // the first scratch is 1.8 s in, until then it is only rumble
+        let y: Vec<f64> = (0..44100).map(|_| v.step(1.0, &mut rng)).collect();
+        let power = |y: &[f64]| y.iter().map(|y| y * y).sum::<f64>() / y.len() as f64;
+        let rumble = power(&y);
+        // the difference of neighbouring samples, a high-pass, holds little
+        let highs: Vec<f64> = y.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(rumble > 1e-4 && power(&highs) < 0.01 * rumble);
+    }
+
+    #[test]
+    fn vinyl_wow_swings_around_its_latency() {
+        let mut v = Vinyl::new(RATE);
+        let mut x = vec![0.0; 256];
+        x[0] = 1.0;
+        let y: Vec<f64> = x.iter().map(|&x| v.wow(x, x).0).collect();
+        let at = (0..y.len()).max_by(|&a, &b| y[a].partial_cmp(&y[b]).unwrap()).unwrap();
+        assert_eq!(at, v.latency());
+    }
+
+    #[test]
+    fn transport_swings_around_its_latency() {
+        let mut rng = rng();
+        let mut t = Transport::new(RATE);
+        // the delay of a ramp is the ramp minus what comes out of the line,
+        // the interpolation is exact on a ramp
+        let mut delays = |wow: (f64, f64), flutter: (f64, f64), rng: &mut Xoshiro256Plus| {
+            t.reset();
+            let y: Vec<f64> = (0..88200)
+                .map(|n| n as f64 - t.step((n as f64, 0.0), wow, flutter, 0.0, 0.0, rng).0)
+                .collect();
+            y[2 * t.latency()..].to_vec()
+        };
+        let center = Transport::new(RATE).latency() as f64;
+
+        // without depth it is a plain delay
+        assert!(delays((0.0, 1.0), (0.0, 10.0), &mut rng).iter().all(|&d| (d - center).abs() < 1e-9));
+
+        // the wow swings the whole of its range, once per period
+        let d = delays((1.0, 1.0), (0.0, 10.0), &mut rng);
+        let (lo, hi) = d.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+        let swing = WOW_SWING / RATE;
+        assert!((hi - center - swing).abs() < 0.01 * swing && (center - lo - swing).abs() < 0.01 * swing);
+        let ups = d.windows(2).filter(|w| w[0] < center && w[1] >= center).count();
+        assert_eq!(ups, 2);
+
+        // flutter swings less, faster, and never outside the line
+        let d = delays((1.0, 1.0), (1.0, 20.0), &mut rng);
+        assert!(d.iter().all(|&d| d >= 1.0 && d <= 2.0 * center - 1.0));
+        let f = delays((0.0, 1.0), (1.0, 20.0), &mut rng);
+        let (lo, hi) = f.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+        assert!(hi > center && lo < center && hi - lo <= 2.0 * FLUTTER_SWING / RATE + 1e-9);
+    }
+
+    #[test]
+    fn hiss_is_bright_and_rides_the_signal() {
+        let mut rng = rng();
+        let mut h = Hiss::new(RATE);
+        let power = |y: &[f64]| y.iter().map(|y| y * y).sum::<f64>() / y.len() as f64;
+        let mut noise = |x: f64, rng: &mut Xoshiro256Plus| -> Vec<(f64, f64)> {
+            (0..44100).map(|_| h.step(1.0, (x, x), 0.0, rng)).collect()
+        };
+
+        // steady hiss with nothing on the tape, the channels uncorrelated
+        let quiet = noise(0.0, &mut rng);
+        let l: Vec<f64> = quiet.iter().map(|y| y.0).collect();
+        let r: Vec<f64> = quiet.iter().map(|y| y.1).collect();
+        let hiss = power(&l);
+        assert!(hiss > 0.5 && hiss < 4.0, "{}", hiss);
+        let cross = l.iter().zip(r.iter()).map(|(l, r)| l * r).sum::<f64>() / l.len() as f64;
+        assert!(cross.abs() < 0.05 * hiss);
+
+        // the shelf lifts the highs, the power at 8 kHz over that at 1 kHz,
+        // averaged over 100 ms blocks
+        let band = |freq: f64| {
+            l.chunks(4410).map(|c| {
+                let w = 2.0 * PI * freq * RATE;
+                let (re, im) = c.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, y)| {
+                    (re + y * (w * n as f64).cos(), im + y * (w * n as f64).sin())
+                });
+                re * re + im * im
+            }).sum::<f64>()
+        };
+        assert!(band(8000.0) > 2.0 * band(1000.0));
+
+        // asperity noise grows with the signal on the tape
+        let loud = noise(1.0, &mut rng);
+        let l: Vec<f64> = loud[4410..].iter().map(|y| y.0).collect();
+        assert!(power(&l) > 50.0 * hiss);
+    }
+
+    #[test]
+    fn dropouts_dip_at_their_rate_and_depth() {
+        let mut rng = rng();
+        let mut d = Dropout::new(RATE);
+        assert!((0..44100).all(|n| d.step(n as f64, 0.0, 1.0, &mut rng) == n as f64));
+
+        // on a constant input the low-pass settles, then the output is the
+        // gain of the dip
+        for _ in 0..4410 {
+            d.step(1.0, 0.0, 1.0, &mut rng);
+        }
+        let floor = 10f64.powf(-DROPOUT_DB / 20.0);
+        let mut starts = 0;
+        for _ in 0..(100.0 / RATE) as usize {
+            let dipping = d.remaining > 0;
+            let y = d.step(1.0, 2.0, 1.0, &mut rng);
+            assert!(y >= floor - 1e-12 && y <= 1.0);
+            if !dipping && d.remaining > 0 {
+                starts += 1;
+                let secs = d.len as f64 * RATE;
+                assert!((DROPOUT_MIN..=DROPOUT_MAX).contains(&secs), "{}", secs);
+                assert!(d.depth >= DROPOUT_SPREAD && d.depth <= 1.0);
+            }
+        }
+        // 2 per second while not dipping, the dips take up about a fifth of
+        // the time, so about 164 in 100 s
+        assert!((130..=200).contains(&starts), "{} dips", starts);
+    }
+
+    #[test]
+    fn resync_starts_the_cycles_over_without_a_jump() {
+        let mut rng = rng();
+        let mut t = Transport::new(RATE);
+        let center = t.latency() as f64;
+        let swing = WOW_SWING / RATE;
+        // a quarter of the way into a 1 Hz wow cycle the delay is at its peak
+        let mut n = 0;
+        let mut delay = |t: &mut Transport, rng: &mut Xoshiro256Plus| {
+            n += 1;
+            n as f64 - t.step((n as f64, 0.0), (1.0, 1.0), (0.0, 10.0), 0.0, 0.0, rng).0
+        };
+        let mut d: Vec<f64> = (0..11025).map(|_| delay(&mut t, &mut rng)).collect();
+        assert!((d[11024] - center - swing).abs() < 0.01 * swing);
+        t.resync();
+        d.extend((0..11025).map(|_| delay(&mut t, &mut rng)));
+
+        // the delay glides back instead of jumping to the start of the cycle
+        let most = |d: &[f64]| d.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f64::max);
+        assert!(most(&d[11025..]) < 0.1 * swing, "{}", most(&d[11025..]));
+        // and once it has, the new cycle is a quarter in again
+        assert!((d[22049] - center - swing).abs() < 0.02 * swing);
+
+        // the vinyl rotation starts over the same way
+        let mut v = Vinyl::new(RATE);
+        for _ in 0..4410 {
+            v.step(0.0, &mut rng);
+            v.wow(0.0, 0.0);
+        }
+        v.resync();
+        assert_eq!(v.phase, 0.0);
+        assert!(v.offset.abs() > 0.0);
+    }
+
+    #[test]
+    fn hiss_lows_can_be_shared_by_the_channels() {
+        let mut rng = rng();
+        // correlation of the channels in a band, over 4 s of hiss
+        let mut correlation = |mono_lows: f64, band: &dyn Fn(&mut Biquad)| {
+            let mut h = Hiss::new(RATE);
+            let mut f = [Biquad::new(), Biquad::new()];
+            for f in f.iter_mut() {
+                band(f);
+            }
+            let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+            for _ in 0..(4.0 / RATE) as usize {
+                let (l, r) = h.step(1.0, (0.0, 0.0), mono_lows, &mut rng);
+                let (l, r) = (f[0].step(l), f[1].step(r));
+                lr += l * r;
+                ll += l * l;
+                rr += r * r;
+            }
+            lr / (ll * rr).sqrt()
+        };
+        let lows = |f: &mut Biquad| f.set_lowpass(100.0, 0.707, RATE);
+        let highs = |f: &mut Biquad| f.set_highpass(5000.0, 0.707, RATE);
+        assert!(correlation(1.0, &lows) > 0.8);
+        assert!(correlation(1.0, &highs).abs() < 0.1);
+        assert!(correlation(0.0, &lows).abs() < 0.1);
+    }
+
+    #[test]
+    fn instability_wanders_slowly_within_its_swing() {
+        // the wander alone, as the delay of a ramp minus the center
+        let wander = |seed: u64| -> Vec<f64> {
+            let mut rng = Xoshiro256Plus::seed_from_u64(seed);
+            let mut t = Transport::new(RATE);
+            let center = t.latency() as f64;
+            (0..(10.0 / RATE) as usize)
+                .map(|n| n as f64 - t.step((n as f64, 0.0), (0.0, 1.0), (0.0, 10.0), 1.0, 0.0, &mut rng).0 - center)
+                .skip(2 * center as usize)
+                .collect()
+        };
+        let w = wander(1);
+        let swing = INSTABILITY_SWING / RATE;
+        assert!(w.iter().all(|d| d.abs() <= swing + 1e-9));
+        let power = |y: &[f64]| y.iter().map(|y| y * y).sum::<f64>() / y.len() as f64;
+        assert!(power(&w) > 0.01 * swing * swing);
+
+        // reproducible from the seed, and another seed wanders elsewhere
+        assert_eq!(w, wander(1));
+        assert_ne!(w, wander(2));
+
+        // low-frequency, the change from one sample to the next is tiny
+        let steps: Vec<f64> = w.windows(2).map(|p| p[1] - p[0]).collect();
+        assert!(power(&steps) < 1e-6 * power(&w));
+
+        // and it doesn't repeat, no lag from 0.1 s to 4 s looks like a period
+        let mean = w.iter().sum::<f64>() / w.len() as f64;
+        let similarity = |lag: usize| {
+            let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+            for (a, b) in w.iter().zip(w[lag..].iter()) {
+                ab += (a - mean) * (b - mean);
+                aa += (a - mean) * (a - mean);
+                bb += (b - mean) * (b - mean);
+            }
+            ab / (aa * bb).sqrt()
+        };
+        let lags = (0.1 / RATE) as usize..(4.0 / RATE) as usize;
+        assert!(lags.step_by(441).all(|lag| similarity(lag) < 0.9));
+    }
+
+    #[test]
+    fn stereo_flutter_decorrelates_the_channels() {
+        // the modulation of each channel, as the delay of a ramp minus the
+        // center, with wow, flutter and instability all on
+        let modulation = |stereo: f64| -> (Vec<f64>, Vec<f64>) {
+            let mut rng = rng();
+            let mut t = Transport::new(RATE);
+            let center = t.latency() as f64;
+            (0..(20.0 / RATE) as usize)
+                .map(|n| {
+                    let n = n as f64;
+                    let (l, r) = t.step((n, n), (1.0, 1.0), (1.0, 10.0), 1.0, stereo, &mut rng);
+                    (n - l - center, n - r - center)
+                })
+                .skip(2 * center as usize)
+                .unzip()
+        };
+        let correlation = |l: &[f64], r: &[f64]| {
+            let (lr, ll, rr) = l.iter().zip(r.iter())
+                .fold((0.0, 0.0, 0.0), |(lr, ll, rr), (l, r)| (lr + l * r, ll + l * l, rr + r * r));
+            (lr / (ll * rr).sqrt(), ll, rr)
+        };
+
+        // off, both channels move together, exactly
+        let (l, r) = modulation(0.0);
+        assert!(l == r);
+
+        // all of the way up they are uncorrelated, and as deep as each other
+        let (l, r) = modulation(1.0);
+        let (c, ll, rr) = correlation(&l, &r);
+        assert!(c.abs() < 0.2, "{}", c);
+        assert!(ll / rr > 0.5 && ll / rr < 2.0);
+
+        // half way they are partly correlated
+        let (l, r) = modulation(0.5);
+        let (c, _, _) = correlation(&l, &r);
+        assert!(c > 0.2 && c < 0.9, "{}", c);
+    }
+}
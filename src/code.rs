@@ -0,0 +1,115 @@
+// Serialized parameters, as a byte blob for host preset chunks and as a short
+// copy-pasteable settings code.
+//
+// The blob is a version byte, a parameter count byte and then every parameter
+// as a little-endian f32. A code is the blob in base64 (url-safe, no padding).
+
+const VERSION: u8 = 1;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// serialize parameter values into a blob
+pub fn to_bytes(values: &[f32]) -> Vec<u8> {
+    let mut bytes = vec![VERSION, values.len() as u8];
+    for v in values {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes
+}
+
+/// parameter values of a blob, None if it is malformed or from an unknown
+/// version
+pub fn from_bytes(bytes: &[u8]) -> Option<Vec<f32>> {
+    if bytes.len() < 2 || bytes[0] != VERSION {
+        return None;
+    }
+    let count = bytes[1] as usize;
+    let data = &bytes[2..];
+    if data.len() != count * 4 {
+        return None;
+    }
+    Some(data.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
+
+/// encode parameter values into a settings code
+pub fn encode(values: &[f32]) -> String {
+    to_base64(&to_bytes(values))
+}
+
+/// decode a settings code into parameter values, None if the code is
+/// malformed or from an unknown version
+pub fn decode(code: &str) -> Option<Vec<f32>> {
+    from_bytes(&from_base64(code.trim())?)
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+fn from_base64(code: &str) -> Option<Vec<u8>> {
+    // a lone character left over carries less than a byte
+    if code.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(code.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in code.bytes() {
+        let v = ALPHABET.iter().position(|&a| a == c)? as u32;
+        acc = acc << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits & 0xff) as u8);
+        }
+    }
+    Some(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<f32> {
+        (0..crate::NUM_PARAMS).map(|i| i as f32 / crate::NUM_PARAMS as f32).collect()
+    }
+
+    #[test]
+    fn codes_round_trip_every_parameter() {
+        let values = values();
+        assert_eq!(decode(&encode(&values)), Some(values.clone()));
+        assert_eq!(from_bytes(&to_bytes(&values)), Some(values));
+        // surrounding whitespace from a copy-paste is fine
+        assert!(decode(&format!(" {}\n", encode(&[0.5]))).is_some());
+    }
+
+    #[test]
+    fn blobs_from_another_version_or_count_are_rejected() {
+        let mut bytes = to_bytes(&values());
+        bytes[0] = VERSION + 1;
+        assert_eq!(from_bytes(&bytes), None);
+        let mut bytes = to_bytes(&values());
+        bytes[1] -= 1;
+        assert_eq!(from_bytes(&bytes), None);
+        assert_eq!(from_bytes(&[VERSION]), None);
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        let code = encode(&values());
+        assert_eq!(decode(&code.replacen('A', "+", 1)), None);
+        assert_eq!(decode(&format!("{}=", code)), None);
+        assert_eq!(decode(&code[..code.len() - 1]), None);
+        assert_eq!(decode(""), None);
+        assert_eq!(from_base64("QUJD"), Some(b"ABC".to_vec()));
+        assert_eq!(from_base64("QUJDR"), None);
+    }
+}
@@ -0,0 +1,245 @@
+use rand_xoshiro::Xoshiro256Plus;
+use std::f64::consts;
+
+use crate::artifacts::uniform;
+
+// order of the quantization amount in the update probability
+const QUANT_ORDER: i32 = 8;
+// scales the distance between the input and the held output into the chance
+// of an update during a sample, distance * QUANT_RATE^2 * sample period
+const QUANT_RATE: f64 = 44100.0;
+// largest quantization amount, at 1 the output would never update again
+const QUANT_AMT_MAX: f64 = 0.75;
+// largest crossover amount fed to atanh, which is infinite at 1
+const XOVER_AMT_MAX: f64 = 0.99;
+// where the soft clipper starts bending, as a fraction of its ceiling
+const CLIP_KNEE: f64 = 0.8;
+
+
+// === DIFFERENTIATION =========================================================
+
+/// first order backwards derivative
+/// + x:    input
+/// + x_p:  previous input
+/// + t:    intersample period
+pub fn diff(x: f64, x_p: f64, t: f64) -> f64 {
+    (x - x_p) / t
+}
+
+
+// === MAGNETIC SATURATION =====================================================
+
+/// tungsten, the hardest knee, close to clipping
+pub fn mag_sat_1(x: f64) -> f64 {
+    x / (1.0 + x.powi(4)).powf(0.25)
+}
+
+/// steel, hyperbolic tangent
+pub fn mag_sat_2(x: f64) -> f64 {
+    x.tanh()
+}
+
+/// iron, algebraic sigmoid
+pub fn mag_sat_3(x: f64) -> f64 {
+    x / (1.0 + x * x).sqrt()
+}
+
+/// nickel, arctangent, approaches its limit slowly
+pub fn mag_sat_4(x: f64) -> f64 {
+    (consts::FRAC_PI_2 * x).atan() * consts::FRAC_2_PI
+}
+
+/// magnetite, the softest curve, compressing from the lowest levels
+pub fn mag_sat_5(x: f64) -> f64 {
+    x / (1.0 + x.abs())
+}
+
+/// saturation curve of a material, indexes past the last one get magnetite
+/// + index:    position of the material in TapeMaterial::ALL
+pub fn sat_for(index: usize) -> fn(f64) -> f64 {
+    match index {
+        0 => mag_sat_1,
+        1 => mag_sat_2,
+        2 => mag_sat_3,
+        3 => mag_sat_4,
+        _ => mag_sat_5,
+    }
+}
+
+
+// === CROSSOVER DISTORTION ====================================================
+
+/// digital crossover distortion, flattens the signal around zero like badly
+///     biased class B output stages. Inside the crossover region the signal is
+///     bent by a power curve, which meets the untouched signal outside at
+///     exactly +-w, so there is no jump at the boundary.
+/// + x:    input
+/// + w:    half width of the crossover region
+/// + amt:  amount, 0 - 1, mapped through atanh up to XOVER_AMT_MAX so the
+///   curve stays finite
+pub fn digital_xover(x: f64, w: f64, amt: f64) -> f64 {
+    let a = x.abs();
+    if w <= 0.0 || a >= w {
+        return x;
+    }
+    let k = (amt.clamp(0.0, 1.0) * XOVER_AMT_MAX).atanh();
+    let u = a / w;
+    (w * u * u.powf(k)).copysign(x)
+}
+
+
+// === QUANTIZATION FUNCTION ===================================================
+
+// TODO: move this to dsp_lab
+/// stochastic quantization
+///     simulates quantum nature of magnetic tape magnetization. The output
+///     follows the input with a probability proportional to how far it has
+///     moved during the sample, so the number of updates per second, and the
+///     sound, is the same at any sample rate.
+/// + x:    input
+/// + x_p:  previous output
+/// + t:    intersample period
+/// + q:    quantization amount, 0 - 1, mapped up to QUANT_AMT_MAX so the
+///   output keeps moving
+/// + rng:  reference to random number generator
+pub fn x_quant(x: f64, x_p: f64, t: f64,
+                q: f64, rng: &mut Xoshiro256Plus) -> f64 {
+    // the chance of following during the sample grows with how far the input
+    // is from the held output, and with the length of the sample
+    let p = (x - x_p).abs() * QUANT_RATE * QUANT_RATE * t;
+    let r = uniform(rng);
+    let q = q.clamp(0.0, 1.0) * QUANT_AMT_MAX;
+    if r < p * (1.0 - q).powi(QUANT_ORDER) {
+        return x;
+    }
+    x_p
+}
+
+
+// === PLAYBACK HEAD ===========================================================
+
+/// playback head low-pass, one pole with the cutoff given in Hz so the tone
+/// is the same at any sample rate. Returns the new output.
+/// + x:        input
+/// + y_p:      previous output
+/// + cutoff:   cutoff frequency in Hz, held at 0.45 of the sample rate so
+///   the coefficient stays below 1 and the filter can't become unstable
+/// + t:        intersample period
+pub fn play(x: f64, y_p: f64, cutoff: f64, t: f64) -> f64 {
+    let fc = cutoff.min(0.45 / t);
+    let a = 1.0 - (-2.0 * consts::PI * fc * t).exp();
+    y_p + a * (x - y_p)
+}
+
+
+// === SOFT CLIPPING ===========================================================
+
+/// soft clipper, linear up to a knee and then bending smoothly towards the
+///     ceiling along a tanh, so the ceiling is never exceeded and nothing
+///     below the knee is touched
+/// + x:        input
+/// + ceiling:  highest output magnitude
+pub fn soft_clip(x: f64, ceiling: f64) -> f64 {
+    let knee = ceiling * CLIP_KNEE;
+    let a = x.abs();
+    if a <= knee {
+        return x;
+    }
+    let y = knee + (ceiling - knee) * ((a - knee) / (ceiling - knee)).tanh();
+    y.copysign(x)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_xoshiro::rand_core::SeedableRng;
+
+    const T: f64 = 1.0 / 44100.0;
+
+    /// updates of the quantizer per second on a 100 Hz sine at an amount
+    fn updates(q: f64, t: f64) -> usize {
+        let mut rng = Xoshiro256Plus::seed_from_u64(1);
+        let mut y = 0.0;
+        let mut count = 0;
+        for n in 0..(1.0 / t) as usize {
+            let x = 0.5 * (2.0 * consts::PI * 100.0 * n as f64 * t).sin();
+            let y_n = x_quant(x, y, t, q, &mut rng);
+            if y_n != y {
+                count += 1;
+            }
+            y = y_n;
+        }
+        count
+    }
+
+    #[test]
+    fn quantization_keeps_following_the_input() {
+        assert!(updates(0.0, T) > 40000);
+        assert!(updates(0.8, T) < updates(0.5, T) / 2);
+        // all the way up it still moves, at the same rate whatever the
+        // sample rate
+        let full = updates(1.0, T);
+        assert!(full > 100 && full < updates(0.8, T) / 2);
+        let double = updates(1.0, T / 2.0) as f64;
+        assert!((double / full as f64 - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn head_filter_stays_bounded_at_the_top() {
+        for &cutoff in &[20000.0, 22050.0, 1e6, f64::INFINITY] {
+            let mut y = 0.0;
+            for n in 0..44100 {
+                let x = 0.5 + 0.5 * (2.0 * consts::PI * 15000.0 * n as f64 * T).sin();
+                y = play(x, y, cutoff, T);
+                assert!(y.is_finite() && y > -0.5 && y < 1.5, "cutoff {}", cutoff);
+            }
+        }
+    }
+
+    #[test]
+    fn crossover_bends_only_inside_its_region() {
+        let w = 0.1;
+        for &amt in [0.0, 0.5, 1.0].iter() {
+            let mut prev = digital_xover(-1.0, w, amt);
+            for n in -1000..=1000 {
+                let x = n as f64 / 1000.0;
+                let y = digital_xover(x, w, amt);
+                assert!(y.is_finite() && y >= prev, "{} {}", amt, x);
+                assert_eq!(digital_xover(-x, w, amt), -y);
+                assert!(y.abs() <= x.abs());
+                if x.abs() >= w {
+                    assert_eq!(y, x);
+                }
+                prev = y;
+            }
+            // no jump at the edges of the region
+            assert!((digital_xover(w - 1e-9, w, amt) - w).abs() < 1e-6);
+        }
+        // no amount leaves the signal alone, more flattens it further
+        assert_eq!(digital_xover(0.05, w, 0.0), 0.05);
+        assert!(digital_xover(0.05, w, 1.0) < digital_xover(0.05, w, 0.5));
+    }
+
+    #[test]
+    fn soft_clip_holds_the_ceiling_and_spares_the_knee() {
+        for &ceiling in [0.25, 1.0].iter() {
+            let mut prev = soft_clip(-100.0, ceiling);
+            for n in -10000..=10000 {
+                let x = n as f64 / 100.0;
+                let y = soft_clip(x, ceiling);
+                assert!(y.abs() < ceiling || (y.abs() - ceiling).abs() < 1e-12);
+                assert!(y >= prev);
+                assert_eq!(soft_clip(-x, ceiling), -y);
+                if x.abs() <= ceiling * CLIP_KNEE {
+                    assert_eq!(y, x);
+                }
+                prev = y;
+            }
+            // the bend starts with the slope of the straight part
+            let knee = ceiling * CLIP_KNEE;
+            let slope = (soft_clip(knee + 1e-7, ceiling) - knee) / 1e-7;
+            assert!((slope - 1.0).abs() < 1e-3);
+        }
+    }
+}
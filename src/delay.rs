@@ -0,0 +1,129 @@
+// Delay line shared by everything that needs to look back in time.
+
+/// how fractional delays are read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// cheap, but dulls the highs at fractional delays
+    Linear,
+    /// flat magnitude, the phase is slightly off and it is stateful, so an
+    /// allpass read is meant for one tap moving slowly
+    Allpass,
+    /// 4-point, 3rd-order hermite, the best for modulated delays
+    Hermite,
+}
+
+/// circular delay line, allocated once up front
+pub struct DelayLine {
+    buf: Vec<f64>,
+    pos: usize,     // index of the most recent sample
+    ap_y: f64,      // previous output of the allpass interpolator
+}
+
+impl DelayLine {
+    /// + max_delay:    longest delay that will be read, in samples
+    pub fn new(max_delay: usize) -> Self {
+        // room for the extra points the interpolators look at
+        DelayLine { buf: vec![0.0; max_delay + 4], pos: 0, ap_y: 0.0 }
+    }
+
+    /// longest delay that can be read, in samples
+    pub fn max_delay(&self) -> usize {
+        self.buf.len() - 4
+    }
+
+    pub fn push(&mut self, x: f64) {
+        self.pos = (self.pos + 1) % self.buf.len();
+        self.buf[self.pos] = x;
+    }
+
+    /// sample at an integer delay, 0 is the most recently pushed one
+    pub fn read(&self, delay: usize) -> f64 {
+        let len = self.buf.len();
+        self.buf[(self.pos + len - delay.min(len - 1)) % len]
+    }
+
+    /// sample at a fractional delay
+    /// + delay:    delay in samples, 0 up to max_delay
+    /// + interp:   interpolation type
+    pub fn read_frac(&mut self, delay: f64, interp: Interpolation) -> f64 {
+        let delay = delay.clamp(0.0, self.max_delay() as f64);
+        let whole = delay.floor() as usize;
+        let frac = delay - whole as f64;
+        let x0 = self.read(whole);
+        let x1 = self.read(whole + 1);
+        match interp {
+            Interpolation::Linear => x0 + frac * (x1 - x0),
+            Interpolation::Allpass => {
+                let eta = (1.0 - frac) / (1.0 + frac);
+                self.ap_y = eta * x0 + x1 - eta * self.ap_y;
+                self.ap_y
+            }
+            Interpolation::Hermite => {
+                let xm1 = if whole > 0 { self.read(whole - 1) } else { x0 };
+                let x2 = self.read(whole + 2);
+                let c1 = 0.5 * (x1 - xm1);
+                let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+                let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+                ((c3 * frac + c2) * frac + c1) * frac + x0
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for v in self.buf.iter_mut() {
+            *v = 0.0;
+        }
+        self.ap_y = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// a slow sine pushed through a line, and the same sine a fractional
+    /// delay back, as the reference
+    fn sine_error(interp: Interpolation, delay: f64) -> f64 {
+        let w = 2.0 * PI * 0.01;
+        let mut line = DelayLine::new(16);
+        let mut err: f64 = 0.0;
+        for n in 0..2000 {
+            line.push((w * n as f64).sin());
+            let y = line.read_frac(delay, interp);
+            if n >= 1000 {
+                err = err.max((y - (w * (n as f64 - delay)).sin()).abs());
+            }
+        }
+        err
+    }
+
+    #[test]
+    fn integer_reads_wrap_around() {
+        let mut line = DelayLine::new(3);
+        for x in 0..20 {
+            line.push(x as f64);
+        }
+        assert_eq!(line.max_delay(), 3);
+        assert_eq!(line.read(0), 19.0);
+        assert_eq!(line.read(3), 16.0);
+        line.reset();
+        assert_eq!(line.read(0), 0.0);
+    }
+
+    #[test]
+    fn fractional_reads_match_the_reference() {
+        let mut line = DelayLine::new(8);
+        for x in 0..8 {
+            line.push(x as f64);
+        }
+        // a ramp is exact for linear interpolation
+        assert!((line.read_frac(2.25, Interpolation::Linear) - 4.75).abs() < 1e-12);
+
+        for &delay in &[2.25, 3.5, 5.9] {
+            assert!(sine_error(Interpolation::Linear, delay) < 1e-3);
+            assert!(sine_error(Interpolation::Hermite, delay) < 1e-5);
+            assert!(sine_error(Interpolation::Allpass, delay) < 1e-3);
+        }
+    }
+}
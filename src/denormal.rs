@@ -0,0 +1,97 @@
+// Flush-to-zero mode for the duration of a block. State decaying through a
+// long silent passage would otherwise sink into the subnormal range, where
+// every operation on it costs many times the usual. The filters flush their
+// own state, this also covers the hysteresis model, whose state can't be
+// reached from outside.
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+use std::arch::asm;
+
+// mode bits that flush subnormals, MXCSR flush-to-zero and
+// denormals-are-zero on x86-64, FPCR FZ on AArch64
+#[cfg(target_arch = "x86_64")]
+const FLUSH: usize = 0x8040;
+#[cfg(target_arch = "aarch64")]
+const FLUSH: usize = 1 << 24;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const FLUSH: usize = 0;
+
+/// flushes subnormals to zero while alive and restores the previous mode
+/// when dropped, so the host thread gets its own mode back. Does nothing on
+/// targets other than x86-64 and AArch64.
+pub struct FlushToZero {
+    prev: usize,
+}
+
+impl FlushToZero {
+    pub fn new() -> Self {
+        let prev = mode();
+        set_mode(prev | FLUSH);
+        FlushToZero { prev }
+    }
+}
+
+impl Drop for FlushToZero {
+    fn drop(&mut self) {
+        set_mode(self.prev);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn mode() -> usize {
+    let mut csr: u32 = 0;
+    // only stores MXCSR to csr
+    unsafe { asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack, preserves_flags)) };
+    csr as usize
+}
+
+#[cfg(target_arch = "x86_64")]
+fn set_mode(mode: usize) {
+    let csr = mode as u32;
+    // only loads MXCSR from csr
+    unsafe { asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly, preserves_flags)) };
+}
+
+#[cfg(target_arch = "aarch64")]
+fn mode() -> usize {
+    let fpcr: u64;
+    // only reads FPCR
+    unsafe { asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack, preserves_flags)) };
+    fpcr as usize
+}
+
+#[cfg(target_arch = "aarch64")]
+fn set_mode(mode: usize) {
+    // only writes FPCR
+    unsafe { asm!("msr fpcr, {}", in(reg) mode as u64, options(nomem, nostack, preserves_flags)) };
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn mode() -> usize {
+    0
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn set_mode(_mode: usize) {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn subnormals_flush_only_while_alive() {
+        let quarter = || black_box(f64::MIN_POSITIVE) / black_box(4.0);
+        assert!(quarter() > 0.0);
+        {
+            let _ftz = FlushToZero::new();
+            assert_eq!(quarter(), 0.0);
+            // a nested guard hands back the flushing mode, not the host's
+            drop(FlushToZero::new());
+            assert_eq!(quarter(), 0.0);
+        }
+        assert!(quarter() > 0.0);
+    }
+}
@@ -0,0 +1,210 @@
+// Envelope followers, used by the level-dependent parts of the plugin.
+
+/// peak envelope follower with separate attack and release
+pub struct Envelope {
+    att: f64,   // attack coefficient
+    rel: f64,   // release coefficient
+    y_p: f64,   // previous output
+}
+
+impl Envelope {
+    /// + attack:   attack time in seconds
+    /// + release:  release time in seconds
+    /// + rate:     intersample period
+    pub fn new(attack: f64, release: f64, rate: f64) -> Self {
+        let mut env = Envelope { att: 1.0, rel: 1.0, y_p: 0.0 };
+        env.set_times(attack, release, rate);
+        env
+    }
+
+    /// recompute the coefficients, for example after a sample rate change
+    /// + attack:   attack time in seconds
+    /// + release:  release time in seconds
+    /// + rate:     intersample period
+    pub fn set_times(&mut self, attack: f64, release: f64, rate: f64) {
+        self.att = time_coeff(attack, rate);
+        self.rel = time_coeff(release, rate);
+    }
+
+    /// advance the follower by one sample, returns the current envelope
+    /// + x:    input, rectified internally
+    pub fn step(&mut self, x: f64) -> f64 {
+        let x = x.abs();
+        let coeff = if x > self.y_p { self.att } else { self.rel };
+        self.y_p += coeff * (x - self.y_p);
+        self.y_p
+    }
+
+    /// current envelope, without advancing
+    pub fn get(&self) -> f64 {
+        self.y_p
+    }
+
+    pub fn reset(&mut self) {
+        self.y_p = 0.0;
+    }
+}
+
+/// one-pole smoothing coefficient for a given time constant
+/// + time:     time constant in seconds, 0 means instantaneous
+/// + rate:     intersample period
+pub fn time_coeff(time: f64, rate: f64) -> f64 {
+    if time <= 0.0 {
+        return 1.0;
+    }
+    1.0 - (-rate / time).exp()
+}
+
+// gate: attack and release times in seconds of the key follower, and of the
+// gain opening on the key and closing after it
+const GATE_KEY_ATTACK: f64 = 0.001;
+const GATE_KEY_RELEASE: f64 = 0.05;
+const GATE_ATTACK: f64 = 0.005;
+const GATE_RELEASE: f64 = 0.1;
+
+/// gate keyed by another signal, opens quickly when the key rises above the
+/// threshold and fades out once it has stayed below it
+pub struct Gate {
+    key: Envelope,
+    gain: Envelope,
+}
+
+impl Gate {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        Gate {
+            key: Envelope::new(GATE_KEY_ATTACK, GATE_KEY_RELEASE, rate),
+            gain: Envelope::new(GATE_ATTACK, GATE_RELEASE, rate),
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.key.set_times(GATE_KEY_ATTACK, GATE_KEY_RELEASE, rate);
+        self.gain.set_times(GATE_ATTACK, GATE_RELEASE, rate);
+    }
+
+    /// advance the gate by one sample, returns its gain, 0 - 1
+    /// + key:          keying signal, rectified internally
+    /// + threshold:    level the key opens the gate at, linear
+    pub fn step(&mut self, key: f64, threshold: f64) -> f64 {
+        let open = self.key.step(key) >= threshold;
+        self.gain.step(if open { 1.0 } else { 0.0 })
+    }
+
+    pub fn reset(&mut self) {
+        self.key.reset();
+        self.gain.reset();
+    }
+}
+
+// how far above the floor an input block has to rise to wake an idle chain,
+// so a signal hovering around the floor doesn't keep switching it (+6 dB)
+const WAKE_MARGIN: f64 = 2.0;
+
+/// silence detector, decides when whole blocks can skip the processing. The
+/// chain goes idle once its input and output have both stayed below the
+/// floor for the hold time, and wakes up as soon as an input block rises
+/// above the floor by WAKE_MARGIN. The state left at that point is as quiet
+/// as the output was, so processing picks up again without a click.
+pub struct Idle {
+    floor: f64,     // linear
+    hold: f64,      // seconds
+    len: usize,     // hold time in samples
+    quiet: usize,   // samples the input and output have been below the floor
+}
+
+impl Idle {
+    /// + floor:    level the input and output have to stay below, linear, 0
+    ///   never goes idle
+    /// + hold:     how long they have to stay below it, in seconds
+    /// + rate:     intersample period
+    pub fn new(floor: f64, hold: f64, rate: f64) -> Self {
+        let mut idle = Idle { floor, hold, len: 0, quiet: 0 };
+        idle.set_rate(rate);
+        idle
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.len = (self.hold / rate).round() as usize;
+    }
+
+    pub fn set_floor(&mut self, floor: f64) {
+        self.floor = floor;
+    }
+
+    /// whether the next block can be skipped, checked before processing it
+    /// + peak_in:  input peak of the block
+    pub fn skip(&mut self, peak_in: f64) -> bool {
+        if peak_in > self.floor * WAKE_MARGIN {
+            self.quiet = 0;
+        }
+        self.quiet >= self.len && self.floor > 0.0
+    }
+
+    /// account for a block that was processed
+    /// + peak_in:  input peak of the block
+    /// + peak_out: output peak of the block
+    /// + samples:  length of the block
+    pub fn update(&mut self, peak_in: f64, peak_out: f64, samples: usize) {
+        if peak_in < self.floor && peak_out < self.floor {
+            self.quiet = self.quiet.saturating_add(samples);
+        } else {
+            self.quiet = 0;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.quiet = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 1.0 / 1000.0;
+    const FLOOR: f64 = 1e-5;
+
+    /// an idle detector with a 1 s hold, fed blocks of 100 samples
+    fn idle() -> Idle {
+        Idle::new(FLOOR, 1.0, RATE)
+    }
+
+    /// feed processed blocks at a level, returns how many were skipped
+    fn feed(idle: &mut Idle, level: f64, blocks: usize) -> usize {
+        let mut skipped = 0;
+        for _ in 0..blocks {
+            if idle.skip(level) {
+                skipped += 1;
+            } else {
+                idle.update(level, level, 100);
+            }
+        }
+        skipped
+    }
+
+    #[test]
+    fn idle_after_the_hold_time() {
+        let mut idle = idle();
+        assert_eq!(feed(&mut idle, 0.0, 10), 0);
+        assert_eq!(feed(&mut idle, 0.0, 10), 10);
+        // a single loud processed block restarts the hold
+        idle.reset();
+        assert_eq!(feed(&mut idle, 0.0, 9), 0);
+        assert_eq!(feed(&mut idle, 0.1, 1), 0);
+        assert_eq!(feed(&mut idle, 0.0, 10), 0);
+    }
+
+    #[test]
+    fn idle_wakes_above_the_margin() {
+        let mut idle = idle();
+        feed(&mut idle, 0.0, 10);
+        // hovering around the floor doesn't wake it
+        assert_eq!(feed(&mut idle, 1.5 * FLOOR, 10), 10);
+        // a signal does, on its first block
+        assert!(!idle.skip(0.1));
+        // without a floor it never goes idle
+        idle.set_floor(0.0);
+        assert_eq!(feed(&mut idle, 0.0, 100), 0);
+    }
+}
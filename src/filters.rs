@@ -0,0 +1,296 @@
+// Linear filters shared by the processing stages.
+
+use std::f64::consts::PI;
+
+use crate::delay::DelayLine;
+
+// states below this are flushed to zero, to keep long silent passages from
+// filling the filters with denormals
+const DENORMAL: f64 = 1e-30;
+
+/// flush a denormal to zero
+pub fn flush(x: f64) -> f64 {
+    if x.abs() < DENORMAL { 0.0 } else { x }
+}
+
+/// one-pole low-pass, the high-passed signal is the input minus its output
+pub struct OnePole {
+    coeff: f64,
+    y_p: f64,   // previous output
+}
+
+impl OnePole {
+    /// + cutoff:   cutoff frequency in Hz
+    /// + rate:     intersample period
+    pub fn new(cutoff: f64, rate: f64) -> Self {
+        let mut f = OnePole { coeff: 1.0, y_p: 0.0 };
+        f.set_cutoff(cutoff, rate);
+        f
+    }
+
+    /// + cutoff:   cutoff frequency in Hz
+    /// + rate:     intersample period
+    pub fn set_cutoff(&mut self, cutoff: f64, rate: f64) {
+        self.coeff = 1.0 - (-2.0 * PI * cutoff * rate).exp();
+    }
+
+    /// advance by one sample, returns the low-passed signal
+    pub fn step(&mut self, x: f64) -> f64 {
+        self.y_p = flush(self.y_p + self.coeff * (x - self.y_p));
+        self.y_p
+    }
+
+    /// current output, without advancing
+    pub fn get(&self) -> f64 {
+        self.y_p
+    }
+
+    pub fn reset(&mut self) {
+        self.y_p = 0.0;
+    }
+}
+
+/// biquad in transposed direct form II, coefficients normalized by a0
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    s1: f64,
+    s2: f64,
+}
+
+impl Biquad {
+    /// pass-through biquad, set the response with one of the designs
+    pub fn new() -> Self {
+        Biquad { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0, s1: 0.0, s2: 0.0 }
+    }
+
+    /// set raw coefficients, already normalized by a0
+    pub fn set_coeffs(&mut self, b0: f64, b1: f64, b2: f64, a1: f64, a2: f64) {
+        self.b0 = b0;
+        self.b1 = b1;
+        self.b2 = b2;
+        self.a1 = a1;
+        self.a2 = a2;
+    }
+
+    /// second order low-pass (RBJ cookbook)
+    /// + cutoff:   cutoff frequency in Hz
+    /// + q:        resonance, 0.707 for butterworth
+    /// + rate:     intersample period
+    pub fn set_lowpass(&mut self, cutoff: f64, q: f64, rate: f64) {
+        let (cos, alpha) = omega(cutoff, q, rate);
+        let b1 = 1.0 - cos;
+        self.set_normalized(b1 * 0.5, b1, b1 * 0.5, 1.0 + alpha, -2.0 * cos, 1.0 - alpha);
+    }
+
+    /// second order high-pass (RBJ cookbook)
+    /// + cutoff:   cutoff frequency in Hz
+    /// + q:        resonance, 0.707 for butterworth
+    /// + rate:     intersample period
+    pub fn set_highpass(&mut self, cutoff: f64, q: f64, rate: f64) {
+        let (cos, alpha) = omega(cutoff, q, rate);
+        let b1 = -(1.0 + cos);
+        self.set_normalized(-b1 * 0.5, b1, -b1 * 0.5, 1.0 + alpha, -2.0 * cos, 1.0 - alpha);
+    }
+
+    /// second order high shelf (RBJ cookbook), the designs for +g and -g dB
+    /// are exact inverses of each other
+    /// + cutoff:   shelf midpoint frequency in Hz
+    /// + q:        shelf slope, 0.707 for a smooth shelf
+    /// + gain:     shelf gain in dB
+    /// + rate:     intersample period
+    pub fn set_high_shelf(&mut self, cutoff: f64, q: f64, gain: f64, rate: f64) {
+        let (cos, alpha) = omega(cutoff, q, rate);
+        let a = 10f64.powf(gain / 40.0);
+        let sq = 2.0 * a.sqrt() * alpha;
+        self.set_normalized(
+            a * ((a + 1.0) + (a - 1.0) * cos + sq),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - sq),
+            (a + 1.0) - (a - 1.0) * cos + sq,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - sq,
+        );
+    }
+
+    /// second order peaking EQ (RBJ cookbook), the designs for +g and -g dB
+    /// are exact inverses of each other
+    /// + cutoff:   center frequency in Hz
+    /// + q:        bandwidth, higher is narrower
+    /// + gain:     gain at the center in dB
+    /// + rate:     intersample period
+    pub fn set_peaking(&mut self, cutoff: f64, q: f64, gain: f64, rate: f64) {
+        let (cos, alpha) = omega(cutoff, q, rate);
+        let a = 10f64.powf(gain / 40.0);
+        self.set_normalized(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        );
+    }
+
+    pub fn step(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.s1;
+        self.s1 = flush(self.b1 * x - self.a1 * y + self.s2);
+        self.s2 = flush(self.b2 * x - self.a2 * y);
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+
+    fn set_normalized(&mut self, b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) {
+        self.set_coeffs(b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0);
+    }
+}
+
+impl Default for Biquad {
+    fn default() -> Self {
+        Biquad::new()
+    }
+}
+
+// cosine of the normalized frequency and the RBJ alpha term, the cutoff is
+// kept below Nyquist so the designs stay stable
+fn omega(cutoff: f64, q: f64, rate: f64) -> (f64, f64) {
+    let w = 2.0 * PI * (cutoff * rate).min(0.49);
+    (w.cos(), w.sin() / (2.0 * q))
+}
+
+/// schroeder allpass, a delay with feedback and feedforward that keeps the
+/// magnitude flat while smearing the phase, used for decorrelation
+pub struct Allpass {
+    line: DelayLine,
+    delay: usize,
+    g: f64,
+}
+
+impl Allpass {
+    /// + delay:    delay in samples, at least 1
+    /// + g:        feedback gain, below 1 in magnitude
+    pub fn new(delay: usize, g: f64) -> Self {
+        let delay = delay.max(1);
+        Allpass { line: DelayLine::new(delay), delay, g }
+    }
+
+    pub fn step(&mut self, x: f64) -> f64 {
+        let d = self.line.read(self.delay - 1);
+        let v = x + self.g * d;
+        self.line.push(flush(v));
+        d - self.g * v
+    }
+
+    pub fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+/// playback head gap loss, the head averages the magnetization over its gap,
+/// that is a moving average over the time the tape takes to cross the gap.
+/// The response is a |sinc|, with the first null at tape speed / gap length.
+pub struct GapLoss {
+    line: DelayLine,
+}
+
+impl GapLoss {
+    /// + max_len:  longest averaging window in samples
+    pub fn new(max_len: usize) -> Self {
+        GapLoss { line: DelayLine::new(max_len.max(1)) }
+    }
+
+    /// + x:    input
+    /// + len:  averaging window in samples, fractional, 1 is a pass-through
+    pub fn step(&mut self, x: f64, len: f64) -> f64 {
+        self.line.push(x);
+        let len = len.clamp(1.0, self.line.max_delay() as f64);
+        let whole = len.floor() as usize;
+        let frac = len - whole as f64;
+        let mut sum = 0.0;
+        for i in 0..whole {
+            sum += self.line.read(i);
+        }
+        sum += frac * self.line.read(whole);
+        sum / len
+    }
+
+    pub fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    const RATE: f64 = 1.0 / 44100.0;
+
+    /// gain of a biquad for a sine at freq, after it has settled
+    fn gain_at(f: &mut Biquad, freq: f64) -> f64 {
+        let w = 2.0 * PI * freq * RATE;
+        let mut peak: f64 = 0.0;
+        for n in 0..8820 {
+            let y = f.step((w * n as f64).cos());
+            if n >= 4410 {
+                peak = peak.max(y.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn one_pole_step_response() {
+        let mut f = OnePole::new(1000.0, RATE);
+        let a = 1.0 - (-2.0 * PI * 1000.0 * RATE).exp();
+        for n in 0..100 {
+            let expected = 1.0 - (1.0 - a).powi(n + 1);
+            assert!((f.step(1.0) - expected).abs() < 1e-12);
+        }
+        f.reset();
+        assert_eq!(f.get(), 0.0);
+    }
+
+    #[test]
+    fn biquad_impulse_follows_the_coefficients() {
+        let mut f = Biquad::new();
+        f.set_coeffs(0.5, 0.25, 0.125, -0.5, 0.25);
+        let h: Vec<f64> = (0..4).map(|n| f.step(if n == 0 { 1.0 } else { 0.0 })).collect();
+        // y[n] = b0 x[n] + b1 x[n-1] + b2 x[n-2] - a1 y[n-1] - a2 y[n-2]
+        let h1 = 0.25 + 0.5 * h[0];
+        let h2 = 0.125 + 0.5 * h1 - 0.25 * h[0];
+        let h3 = 0.5 * h2 - 0.25 * h1;
+        assert_eq!(h[0], 0.5);
+        assert!((h[1] - h1).abs() < 1e-15);
+        assert!((h[2] - h2).abs() < 1e-15);
+        assert!((h[3] - h3).abs() < 1e-15);
+        f.reset();
+        assert_eq!(f.step(0.0), 0.0);
+    }
+
+    #[test]
+    fn biquad_lowpass_and_highpass_responses() {
+        let mut lp = Biquad::new();
+        lp.set_lowpass(1000.0, FRAC_1_SQRT_2, RATE);
+        assert!((gain_at(&mut lp, 0.0) - 1.0).abs() < 1e-3);
+        lp.reset();
+        assert!((gain_at(&mut lp, 1000.0) - FRAC_1_SQRT_2).abs() < 1e-2);
+        lp.reset();
+        assert!(gain_at(&mut lp, 10000.0) < 0.02);
+
+        let mut hp = Biquad::new();
+        hp.set_highpass(1000.0, FRAC_1_SQRT_2, RATE);
+        assert!((gain_at(&mut hp, 1000.0) - FRAC_1_SQRT_2).abs() < 1e-2);
+        hp.reset();
+        assert!((gain_at(&mut hp, 15000.0) - 1.0).abs() < 1e-2);
+        hp.reset();
+        assert!(gain_at(&mut hp, 50.0) < 0.01);
+    }
+}
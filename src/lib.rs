@@ -2,37 +2,628 @@
 #[macro_use]
 extern crate vst;
 
+use vst::api::TimeInfoFlags;
 use vst::buffer::AudioBuffer;
-use vst::plugin::{Category, Info, Plugin, PluginParameters};
+use vst::channels::{ChannelInfo, SpeakerArrangementType, StereoChannel, StereoConfig};
+use vst::host::Host;
+use vst::plugin::{Category, HostCallback, Info, Plugin, PluginParameters};
 use vst::util::AtomicFloat;
 
+use rand_xoshiro::Xoshiro256Plus;
+use rand_xoshiro::rand_core::SeedableRng;
+
+use std::f64::consts::FRAC_1_SQRT_2;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use dsp_lab::emulation::Hysteresis;
 use dsp_lab::traits::Process;
 use dsp_lab::utils::math::{x_fade};
 use dsp_lab::utils::conversion::{db_to_gain};
 
-// mod compute; // contains processing functions
+pub mod compute; // contains processing functions
+mod artifacts;  // tape artifacts
+mod code;       // shareable settings codes
+pub mod delay;  // delay line
+mod denormal;   // flush-to-zero mode
+mod envelope;   // envelope followers
+pub mod filters; // linear filters
+mod loudness;   // loudness estimation
+mod meter;      // output metering
+pub mod modes;  // typed modes
+pub mod oversample; // oversampling
+mod presets;    // factory presets
+pub mod resample; // fixed internal rate
+mod smoother;   // parameter smoothing
+
+use artifacts::{Dropout, Hiss, Pulse, Splice, Transport, Vinyl};
+use delay::DelayLine;
+use denormal::FlushToZero;
+use envelope::{Envelope, Gate, Idle};
+use filters::{flush, Allpass, Biquad, GapLoss, OnePole};
+use loudness::{Loudness, MeanSquare};
+use meter::TruePeak;
+use modes::{Coupling, Medium, MeterMode, MixLaw, OversamplingFactor, StereoMode, TapeMaterial, TapeSpeed};
+use oversample::Oversampler;
+use resample::FixedRate;
+use smoother::{Bypass, Glide, Recovery, Smoother};
+
+// bounds on the signal at the stage boundaries, far above anything musical
+// but keeping inf out of the recursive parts of the chain: the plugin input
+// and the saturator input (+60 dB, reached with all drive stacked up), the
+// saturator output and the plugin output (+40 dB)
+const IN_LIMIT: f64 = 1e3;
+const SAT_IN_LIMIT: f64 = 1e3;
+const SAT_OUT_LIMIT: f64 = 1e2;
+const OUT_LIMIT: f64 = 1e2;
+
+// number of parameters exposed to the host
+const NUM_PARAMS: i32 = 62;
+// default value of every parameter, by index
+const DEFAULTS: [f32; NUM_PARAMS as usize] = [
+    0.5, 0.5, 0.5, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, // 0 - 9
+    0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.25, // 10 - 19
+    0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 0.6, 0.0, // 20 - 29
+    0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, // 30 - 39
+    1.0, 0.0, 0.5, 0.0, 0.5, 0.0, 0.0, 0.5, 0.0, 0.0, // 40 - 49
+    0.0, 0.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.5, 1.0, 0.0, // 50 - 59
+    0.0, 0.0, // 60 - 61
+];
+
+// highest feedback gain, kept well below unity so the loop can't run away
+const FB_MAX: f64 = 0.5;
+// cutoff of the low-pass on the feedback path in Hz at the default tone, and
+// the range of the tone control
+const FB_CUTOFF: f64 = 4000.0;
+const FB_TONE_MIN: f64 = 500.0;
+const FB_TONE_MAX: f64 = 16000.0;
+// ranges of the wow and flutter rates in Hz
+const WOW_RATE_MIN: f64 = 0.5;
+const WOW_RATE_MAX: f64 = 2.0;
+const FLUTTER_RATE_MIN: f64 = 6.0;
+const FLUTTER_RATE_MAX: f64 = 20.0;
+// attack times of the transient detector envelopes and their shared release,
+// in seconds
+const TRANS_FAST: f64 = 0.0005;
+const TRANS_SLOW: f64 = 0.02;
+const TRANS_RELEASE: f64 = 0.1;
+// auto level detector times in seconds, and the largest gain change it applies
+const LEVEL_ATTACK: f64 = 0.001;
+const LEVEL_RELEASE: f64 = 0.5;
+const AUTO_MAX: f64 = 16.0;
+// silence detection, the default floor the input and output have to stay
+// below (-100 dB), and for how long in seconds, before blocks skip the chain
+const IDLE_FLOOR: f32 = -100.0;
+const IDLE_HOLD: f64 = 0.5;
+// cutoff of the analog input stage in Hz, pulled down at low sample rates so
+// it always sits well below Nyquist
+const ANALOG_CUTOFF: f64 = 18000.0;
+// bias trap low-pass cutoff in Hz, pulled down at low sample rates
+const BIAS_TRAP: f64 = 19000.0;
+// fixed seed of the artifact generator, so renders are reproducible
+const SEED: u64 = 0x4859_5354_4552_4553;
+// highest splice click rate, per second
+const SPLICE_MAX: f64 = 4.0;
+// highest dropout rate, per second
+const DROPOUT_RATE_MAX: f64 = 2.0;
+// range of the hiss level in dB, the bottom of the knob turns it off
+const HISS_MIN: f64 = -90.0;
+const HISS_MAX: f64 = -30.0;
+// range of the noise gate threshold in dBFS, the bottom of the knob turns it
+// off
+const NOISE_GATE_MIN: f64 = -80.0;
+const NOISE_GATE_MAX: f64 = -20.0;
+// delay of the harmonic spread allpass in seconds, and its gain
+const SPREAD_DELAY: f64 = 0.007;
+const SPREAD_G: f64 = 0.6;
+// widest head gap in m, which puts the first gap loss null near 7.6 kHz at
+// 15 ips
+const GAP_MAX: f64 = 50e-6;
+// time constant of the exponential parameter smoothers, and glide time of the
+// linear ones (used for gains in dB), in seconds
+const SMOOTH_TIME: f64 = 0.005;
+const GLIDE_TIME: f64 = 0.02;
+// saturation meter envelope times in seconds, used by auto drive
+const SAT_ATTACK: f64 = 0.01;
+const SAT_RELEASE: f64 = 0.2;
+// input envelope below which the saturation isn't measured (-80 dB)
+const SAT_FLOOR: f64 = 1e-4;
+// auto drive: speed in dB per second per unit of saturation error, the
+// largest drive it applies in dB, and the highest target saturation
+const DRIVE_SPEED: f64 = 12.0;
+const DRIVE_MAX: f64 = 24.0;
+const DRIVE_TARGET_MAX: f64 = 0.5;
+// drive-linked width: drive in dB at which the narrowing is complete, and the
+// share of the side signal removed there at full depth
+const WIDTH_DRIVE: f64 = 24.0;
+const WIDTH_NARROW: f64 = 0.5;
+// drive EQ: midpoint of the tilt shelves in Hz, and their largest tilt in dB
+const DRIVE_EQ_FREQ: f64 = 500.0;
+const DRIVE_EQ_MAX: f64 = 12.0;
+// range of the harmonic high-pass cutoff in Hz, the bottom of the knob turns
+// it off
+const HARM_HPF_MIN: f64 = 20.0;
+const HARM_HPF_MAX: f64 = 300.0;
+// thickness: center of the low-mid band in Hz, its bandwidth, and the largest
+// boost into the saturator in dB, cut again after it
+const THICK_FREQ: f64 = 250.0;
+const THICK_Q: f64 = 0.8;
+const THICK_MAX: f64 = 9.0;
+// head EQ: boost of the record pre-emphasis in dB, cut again by the playback
+// de-emphasis, and the gain in dB and bandwidth of the head bump
+const EMPHASIS_GAIN: f64 = 10.0;
+const BUMP_GAIN: f64 = 3.0;
+const BUMP_Q: f64 = 1.0;
+// bias: largest shift of the operating point, in saturator input units, and
+// the glide time of the shift in seconds, slow enough for the DC blocker
+// after the saturator to follow. Under-bias: half width of the crossover
+// region around zero
+const BIAS_MAX: f64 = 0.25;
+const BIAS_TIME: f64 = 0.05;
+const XOVER_WIDTH: f64 = 0.05;
+// range of the saturation band edges in Hz, the bottom of the low edge and
+// the top of the high edge turn that side off
+const BAND_MIN: f64 = 20.0;
+const BAND_MAX: f64 = 20000.0;
+// no-output status: input level that counts as signal, -60 dB, and output
+// level that counts as silence, -100 dB
+const SIGNAL_FLOOR: f64 = 1e-3;
+const SILENCE_FLOOR: f64 = 1e-5;
+// release time of the true-peak meter, in seconds
+const METER_RELEASE: f64 = 1.5;
+// largest trim applied by loudness match
+const MATCH_MAX: f64 = 4.0;
+// wet amount below which the plugin counts as transparent
+const TRANSPARENT_TOL: f32 = 1e-3;
+// null calibration: length of the noise burst, longest delay searched, both
+// in samples, and the level of the burst, -30 dB
+const CAL_LEN: usize = 16384;
+const CAL_LAG: usize = 2048;
+const CAL_LEVEL: f64 = 0.03;
+// resolution of the numerical transfer curve measurements
+const RAMP_STEPS: usize = 512;
+// steps per quarter of the loop area triangle, and its peak input
+const LOOP_STEPS: usize = 256;
+const LOOP_PEAK: f64 = 1.0;
+const RATIO_DELTA: f64 = 1e-3;
+// cutoff of the AC coupling high-pass in Hz, also used to take the DC off the
+// even harmonics
+const COUPLING_CUTOFF: f64 = 10.0;
+// lookahead of the dynamic stages, in seconds
+const LOOKAHEAD: f64 = 0.002;
+// range the squareness knob is mapped to, the model turns linear towards 0
+// and towards 1 the loop becomes a hard square that flips branch on every
+// sample and oscillates at Nyquist
+const SQ_MIN: f64 = 0.02;
+const SQ_MAX: f64 = 0.95;
+// range of the playback head cutoff in Hz, the top of the knob turns it off
+const HEAD_MIN: f64 = 2000.0;
+const HEAD_MAX: f64 = 20000.0;
+
+// instances created so far, each one gets its own quantizer seed so that two
+// instances on a stereo pair of tracks don't produce the same noise
+static INSTANCES: AtomicU64 = AtomicU64::new(0);
+// instances currently alive, and how many of them it takes for load
+// balancing to switch the oversampling to its economy filters
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+const BALANCE_INSTANCES: usize = 16;
+// widest bus the plugin takes, 7.1, processed as stereo pairs
+const MAX_CHANNELS: usize = 8;
+// host opcode asking it to read the latency and channel counts again
+// (audioMasterIOChanged), the vst crate has no call for it
+const IO_CHANGED: i32 = 13;
+
+// parameter values the sample loop works with, read and mapped once at the
+// start of each block instead of on every sample. For the smoothed and
+// bypassed ones these are the targets they head to.
+#[derive(Clone, Copy)]
+struct BlockParams {
+    sq: f64,
+    coerc: f64,
+    pre_post: f64,      // dB
+    threshold: f64,     // dB
+    dry_wet: f64,
+    equal_power: bool,
+    fb: f64,
+    fb_cutoff: f64,     // Hz
+    transient: bool,
+    ceiling: f64,       // gain
+    auto_level: bool,
+    analog_in: bool,
+    lookahead: bool,
+    trap: bool,
+    loudness_match: bool,
+    wet_match: bool,
+    safety: bool,
+    safety_ceiling: f64,    // gain
+    splice_rate: f64,
+    splice_level: f64,  // gain
+    hiss: f64,          // gain
+    rhythm: f64,        // depth of the beat pulse on the noise, 0 when off
+    hiss_mono_lows: bool,
+    noise_gate: Option<f64>,    // threshold, linear, None when off
+    dropout_rate: f64,
+    dropout_depth: f64,
+    spread: f64,
+    gap: f64,           // s, time the tape takes to cross the head gap
+    clean_blend: f64,
+    dyn_coerc: f64,
+    ac_coupled: bool,
+    auto_drive: bool,
+    drive_target: f64,
+    stereo_mode: StereoMode,
+    link: f64,
+    bias: f64,
+    xover: f64,
+    width_depth: f64,
+    vinyl: bool,
+    wow: (f64, f64),        // depth, rate in Hz
+    flutter: (f64, f64),    // depth, rate in Hz
+    instability: f64,
+    stereo_flutter: f64,
+    even: f64,
+    quant: f64,
+    head_cut: Option<f64>,  // Hz, None when the head filter is off
+    material: TapeMaterial,
+    tp_len: usize,          // samples, center of the wow and flutter
+}
+
+impl BlockParams {
+    fn read(p: &EffectParameters) -> Self {
+        let head_cut = p.head_cut.get() as f64;
+        BlockParams {
+            sq: squareness(p.dbg_sq.get() as f64),
+            coerc: p.dbg_coerc.get() as f64,
+            pre_post: p.pre_post.get() as f64 * 24.0 - 12.0,
+            threshold: p.threshold.get() as f64 * 36.0 - 36.0,
+            dry_wet: p.dry_wet.get() as f64,
+            equal_power: p.mix_law() == MixLaw::EqualPower,
+            fb: p.feedback.get() as f64 * FB_MAX,
+            fb_cutoff: feedback_cutoff(p.feedback_tone.get() as f64),
+            transient: p.transient.get() > 0.5,
+            ceiling: db_to_gain(p.ceiling.get() as f64 * 24.0 - 24.0),
+            auto_level: p.auto_level.get() > 0.5,
+            analog_in: p.analog_in.get() > 0.5,
+            lookahead: p.lookahead.get() > 0.5,
+            trap: p.bias_trap.get() > 0.5,
+            loudness_match: p.loudness_match.get() > 0.5,
+            wet_match: p.wet_match.get() > 0.5,
+            safety: p.safety.get() > 0.5,
+            safety_ceiling: db_to_gain(p.safety_ceiling.get() as f64 * 24.0 - 24.0),
+            splice_rate: p.splice_rate.get() as f64 * SPLICE_MAX,
+            splice_level: db_to_gain(p.splice_level.get() as f64 * 48.0 - 60.0),
+            hiss: hiss_level(p.hiss.get() as f64),
+            rhythm: if p.rhythmic.get() > 0.5 { p.rhythm_depth.get() as f64 } else { 0.0 },
+            hiss_mono_lows: p.hiss_mono_lows.get() > 0.5,
+            noise_gate: noise_gate(p.noise_gate.get() as f64),
+            dropout_rate: p.dropout_rate.get() as f64 * DROPOUT_RATE_MAX,
+            dropout_depth: p.dropout_depth.get() as f64,
+            spread: p.spread.get() as f64,
+            gap: p.gap_loss.get() as f64 * GAP_MAX / p.tape_speed().speed(),
+            clean_blend: p.clean_blend.get() as f64,
+            dyn_coerc: p.dyn_coerc.get() as f64,
+            ac_coupled: p.coupling() == Coupling::AC,
+            auto_drive: p.auto_drive.get() > 0.5,
+            drive_target: p.drive_target.get() as f64 * DRIVE_TARGET_MAX,
+            stereo_mode: p.stereo_mode(),
+            link: p.link.get() as f64,
+            bias: p.bias.get() as f64 * BIAS_MAX,
+            xover: p.xover.get() as f64,
+            width_depth: p.width_depth.get() as f64,
+            vinyl: p.medium() == Medium::Vinyl,
+            wow: (p.wow_depth.get() as f64, wow_rate(p.wow_rate.get() as f64)),
+            flutter: (p.flutter_depth.get() as f64, flutter_rate(p.flutter_rate.get() as f64)),
+            instability: p.instability.get() as f64,
+            stereo_flutter: p.stereo_flutter.get() as f64,
+            even: p.even.get() as f64,
+            quant: p.quant.get() as f64,
+            head_cut: if head_cut < 1.0 { Some(head_cutoff(head_cut)) } else { None },
+            material: p.material(),
+            // set by the chain, it depends on the rate
+            tp_len: 0,
+        }
+    }
+}
 
 // Plugin struct, this is where the processing happens
-struct Effect {
+pub struct Effect {
     // Store a handle to the plugin's parameter object.
     params: Arc<EffectParameters>,
+    host: HostCallback,
+    // parameters for the block being processed
+    block: BlockParams,
+    // latency last reported to the host, in samples
+    reported_latency: usize,
+    // chains for the channel pairs after the first on a multichannel bus,
+    // made when the host resumes
+    buses: Vec<Effect>,
+    // whether this counts as a live instance, those and the probes don't
+    live: bool,
+    // whether the chain is running a single channel, which only needs one
+    // model
+    mono: bool,
 
     // meta
-    // sr: f32,
-    // rate: f32,
+    sr: f64,
+    rate: f64,
     hyst_l: Hysteresis,
     hyst_r: Hysteresis,
+
+    // feedback path, holds the low-passed previous wet output
+    fb_l: OnePole,
+    fb_r: OnePole,
+
+    // transient detector, difference of a fast and a slow envelope
+    trans_fast: Envelope,
+    trans_slow: Envelope,
+
+    // peak detector of the auto level stage
+    level_env: Envelope,
+
+    // gate on the tape noise, keyed by the dry input
+    noise_gate: Gate,
+
+    // silence detection, lets blocks of silence skip the chain
+    idle: Idle,
+
+    // AC coupling, the high-pass is the input minus these low-passes
+    dc_l: OnePole,
+    dc_r: OnePole,
+
+    // even harmonics, low-passes of the rectified signal holding its DC
+    even_l: OnePole,
+    even_r: OnePole,
+
+    // bias, the smoothed operating point shift and low-passes of the
+    // saturated signal holding the DC it causes
+    bias: Smoother,
+    bias_l: OnePole,
+    bias_r: OnePole,
+
+    // analog input stage low-pass
+    in_l: OnePole,
+    in_r: OnePole,
+
+    // bias trap, band-limits the wet signal like the output electronics
+    trap_l: Biquad,
+    trap_r: Biquad,
+
+    // loudness of the input and of the processed output, for loudness match
+    loud_in: Loudness,
+    loud_out: Loudness,
+    // wet gain match, the mean squares of the dry and wet signals
+    ms_dry: MeanSquare,
+    ms_wet: MeanSquare,
+
+    // tape artifacts
+    rng: Xoshiro256Plus,
+    splice: Splice,
+    vinyl: Vinyl,
+    // wow and flutter of the tape transport
+    transport: Transport,
+    // tape hiss and asperity noise
+    hiss: Hiss,
+    pulse: Pulse,
+    // dropouts, each channel with its own
+    dropout_l: Dropout,
+    dropout_r: Dropout,
+    // whether the transport was playing during the last block, and where
+    // it should be at the start of the next one, a position before that
+    // means it jumped back, e.g. a loop wrapped
+    playing: bool,
+    next_pos: f64,
+
+    // stochastic quantization, its previous outputs and generators, seeded
+    // from the instance seed
+    seed: u64,
+    quant_l: f64,
+    quant_r: f64,
+    quant_rng_l: Xoshiro256Plus,
+    quant_rng_r: Xoshiro256Plus,
+
+    // playback head low-pass, previous outputs and the smoothed cutoff
+    head_l: f64,
+    head_r: f64,
+    head_cut: Smoother,
+
+    // playback head gap loss
+    gap_l: GapLoss,
+    gap_r: GapLoss,
+
+    // thickness, the boost in dB the filters are set for, the low-mid boost
+    // before the saturator and the matching cut after it
+    thick_gain: f64,
+    thick_pre: [Biquad; 2],
+    thick_post: [Biquad; 2],
+
+    // head EQ, the speed the filters are set for (None is off), the record
+    // pre-emphasis, the playback de-emphasis and head bump, and the state of
+    // the playback roll-off
+    head_speed: Option<TapeSpeed>,
+    emph_pre: [Biquad; 2],
+    emph_post: [Biquad; 2],
+    bump: [Biquad; 2],
+    roll: [f64; 2],
+
+    // saturation band, the edges in Hz the filters are set for (0 is off),
+    // the band-pass that picks what is saturated, and the remainder delayed
+    // to line up with the saturated band
+    band_lo: f64,
+    band_hi: f64,
+    band_hp: [Biquad; 2],
+    band_lp: [Biquad; 2],
+    band_rest: [DelayLine; 2],
+
+    // harmonic high-pass, the cutoff in Hz it is set for and the filters on
+    // the harmonics (wet minus dry)
+    harm_cut: f64,
+    harm_l: Biquad,
+    harm_r: Biquad,
+
+    // decorrelates the harmonics for harmonic spread
+    spread: Allpass,
+
+    // saturation meter, envelopes of the saturator input and output, and the
+    // drive applied by auto drive in dB
+    sat_in: Envelope,
+    sat_out: Envelope,
+    drive: f64,
+
+    // drive EQ, the tilt in dB the shelves are set for, its make-up gain and
+    // the shelves before and after the saturator
+    eq_tilt: f64,
+    eq_gain: f64,
+    eq_pre: [Biquad; 2],
+    eq_post: [Biquad; 2],
+
+    // parameter smoothers
+    dry_wet: Smoother,
+    pre_post: Smoother,
+    threshold: Smoother,
+    sq: Smoother,
+    coerc: Smoother,
+
+    // click-free switching of the stages that are turned on and off
+    coupling_by: Bypass,
+    analog_by: Bypass,
+    trap_by: Bypass,
+    transient_by: Bypass,
+    match_by: Bypass,
+    wet_match_by: Bypass,
+    safety_by: Bypass,
+    hiss_lows_by: Bypass,
+    level_by: Bypass,
+    // material switching, the curves on either side of the crossfade and the
+    // side it is heading to
+    mat: [TapeMaterial; 2],
+    mat_side: bool,
+    mat_by: Bypass,
+
+    // output meters, the peak holds are published to the parameter object
+    // at the end of each block
+    tp_l: TruePeak,
+    tp_r: TruePeak,
+    peak_hold: Envelope,
+    tp_hold: Envelope,
+    // ballistics the sample-peak meter is set for
+    meter_mode: MeterMode,
+
+    // lookahead delay, for dry left, dry right, wet left, wet right
+    la: [DelayLine; 4],
+    la_len: usize,
+
+    // oversampling of the nonlinear stage, the number of 2x stages it runs
+    // with this block, and the dry signal delayed to line up with it
+    os_l: Oversampler,
+    os_r: Oversampler,
+    os_stages: usize,
+    // or the conversion to the fixed internal rate it runs at instead, and
+    // whether it is on for this block
+    src_l: FixedRate,
+    src_r: FixedRate,
+    src_on: bool,
+    // fade-ins of the models after they were reset by the non-finite guard
+    recover_l: Recovery,
+    recover_r: Recovery,
+    os_dry: [DelayLine; 2],
 }
 
 // Plugin parameters, this is where the UI happens
-struct EffectParameters {
+pub struct EffectParameters {
     pre_post: AtomicFloat,
     dbg_sq: AtomicFloat,
     dbg_coerc: AtomicFloat,
     dry_wet: AtomicFloat,
+    feedback: AtomicFloat,
+    threshold: AtomicFloat,
+    transient: AtomicFloat,
+    ceiling: AtomicFloat,
+    auto_level: AtomicFloat,
+    analog_in: AtomicFloat,
+    lookahead: AtomicFloat,
+    bias_trap: AtomicFloat,
+    loudness_match: AtomicFloat,
+    splice_rate: AtomicFloat,
+    splice_level: AtomicFloat,
+    spread: AtomicFloat,
+    mix_law: AtomicFloat,
+    gap_loss: AtomicFloat,
+    auto_drive: AtomicFloat,
+    drive_target: AtomicFloat,
+    loop_lock: AtomicFloat,
+    clean_blend: AtomicFloat,
+    dyn_coerc: AtomicFloat,
+    dc_coupled: AtomicFloat,
+    quant: AtomicFloat,
+    head_cut: AtomicFloat,
+    material: AtomicFloat,
+    oversampling: AtomicFloat,
+    feedback_tone: AtomicFloat,
+    even: AtomicFloat,
+    stereo_mode: AtomicFloat,
+    width_depth: AtomicFloat,
+    load_balance: AtomicFloat,
+    drive_eq: AtomicFloat,
+    medium: AtomicFloat,
+    harm_hpf: AtomicFloat,
+    thickness: AtomicFloat,
+    fixed_rate: AtomicFloat,
+    meter_mode: AtomicFloat,
+    band_low: AtomicFloat,
+    band_high: AtomicFloat,
+    wow_depth: AtomicFloat,
+    wow_rate: AtomicFloat,
+    flutter_depth: AtomicFloat,
+    flutter_rate: AtomicFloat,
+    hiss: AtomicFloat,
+    head_eq: AtomicFloat,
+    tape_speed: AtomicFloat,
+    link: AtomicFloat,
+    bias: AtomicFloat,
+    xover: AtomicFloat,
+    dropout_rate: AtomicFloat,
+    dropout_depth: AtomicFloat,
+    wet_match: AtomicFloat,
+    safety: AtomicFloat,
+    safety_ceiling: AtomicFloat,
+    rhythmic: AtomicFloat,
+    rhythm_depth: AtomicFloat,
+    hiss_mono_lows: AtomicFloat,
+    instability: AtomicFloat,
+    noise_gate: AtomicFloat,
+    stereo_flutter: AtomicFloat,
+
+    // meters, written by the audio thread, not exposed as parameters
+    sample_peak: AtomicFloat,
+    true_peak: AtomicFloat,
+    saturation: AtomicFloat,
+    no_output: AtomicBool,
+
+    // level in dB below which silent blocks skip the chain, set from the UI
+    // thread, not exposed as a parameter
+    idle_floor: AtomicFloat,
+
+    // set from the UI thread to have the audio thread clear the DSP state,
+    // or only re-sync the modulation, at the start of the next block
+    reset_pending: AtomicBool,
+    resync_pending: AtomicBool,
+
+    // parameters locked against host automation, one bit per index
+    locks: AtomicU64,
+
+    // A/B comparison snapshots and the one currently live
+    slots: [Vec<AtomicFloat>; 2],
+    active_slot: AtomicUsize,
+
+    // factory preset last selected through the program API
+    program: AtomicUsize,
+}
+
+/// A/B comparison slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
 }
 
 // All plugins using the `vst` crate will either need to implement the `Default`
@@ -41,155 +632,3844 @@ struct EffectParameters {
 // 0.5 means it's halfway up.
 impl Default for Effect {
     fn default() -> Effect {
+        Effect::chain(Arc::new(EffectParameters::default()), true)
+    }
+}
+
+impl Effect {
+    // a chain running on the given parameters. Only a live one counts as an
+    // instance for load balancing, until it is dropped, the chains for the
+    // other pairs of a bus and the probes don't.
+    fn chain(params: Arc<EffectParameters>, live: bool) -> Effect {
+        if live {
+            LIVE.fetch_add(1, Ordering::Relaxed);
+        }
         Effect {
-            params: Arc::new(EffectParameters::default()),
+            block: BlockParams::read(&params),
+            params,
+            host: HostCallback::default(),
+            reported_latency: 0,
+            buses: Vec::new(),
+            live,
+            mono: false,
 
-            // sr: 44100.0,
-            // rate: 1.0/44100.0,
+            sr: 44100.0,
+            rate: 1.0/44100.0,
             hyst_l: Hysteresis::new(),
             hyst_r: Hysteresis::new(),
+
+            fb_l: OnePole::new(FB_CUTOFF, 1.0/44100.0),
+            fb_r: OnePole::new(FB_CUTOFF, 1.0/44100.0),
+
+            trans_fast: Envelope::new(TRANS_FAST, TRANS_RELEASE, 1.0/44100.0),
+            trans_slow: Envelope::new(TRANS_SLOW, TRANS_RELEASE, 1.0/44100.0),
+
+            level_env: Envelope::new(LEVEL_ATTACK, LEVEL_RELEASE, 1.0/44100.0),
+
+            noise_gate: Gate::new(1.0/44100.0),
+
+            idle: Idle::new(db_to_gain(IDLE_FLOOR as f64), IDLE_HOLD, 1.0/44100.0),
+
+            dc_l: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+            dc_r: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+
+            even_l: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+            even_r: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+
+            bias: Smoother::new(BIAS_TIME, 1.0/44100.0, Glide::Exponential),
+            bias_l: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+            bias_r: OnePole::new(COUPLING_CUTOFF, 1.0/44100.0),
+
+            in_l: OnePole::new(analog_cutoff(44100.0), 1.0/44100.0),
+            in_r: OnePole::new(analog_cutoff(44100.0), 1.0/44100.0),
+
+            trap_l: bias_trap(44100.0),
+            trap_r: bias_trap(44100.0),
+
+            loud_in: Loudness::new(1.0/44100.0),
+            loud_out: Loudness::new(1.0/44100.0),
+            ms_dry: MeanSquare::new(1.0/44100.0),
+            ms_wet: MeanSquare::new(1.0/44100.0),
+
+            rng: Xoshiro256Plus::seed_from_u64(SEED),
+            splice: Splice::new(1.0/44100.0),
+            vinyl: Vinyl::new(1.0/44100.0),
+            transport: Transport::new(1.0/44100.0),
+            hiss: Hiss::new(1.0/44100.0),
+            pulse: Pulse::new(),
+            dropout_l: Dropout::new(1.0/44100.0),
+            dropout_r: Dropout::new(1.0/44100.0),
+            playing: false,
+            next_pos: 0.0,
+
+            seed: SEED,
+            quant_l: 0.0,
+            quant_r: 0.0,
+            quant_rng_l: Xoshiro256Plus::seed_from_u64(SEED),
+            quant_rng_r: Xoshiro256Plus::seed_from_u64(SEED + 1),
+
+            head_l: 0.0,
+            head_r: 0.0,
+            head_cut: Smoother::new(SMOOTH_TIME, 1.0/44100.0, Glide::Exponential),
+
+            gap_l: GapLoss::new(gap_len(44100.0)),
+            gap_r: GapLoss::new(gap_len(44100.0)),
+
+            thick_gain: 0.0,
+            thick_pre: [Biquad::new(), Biquad::new()],
+            thick_post: [Biquad::new(), Biquad::new()],
+
+            head_speed: None,
+            emph_pre: [Biquad::new(), Biquad::new()],
+            emph_post: [Biquad::new(), Biquad::new()],
+            bump: [Biquad::new(), Biquad::new()],
+            roll: [0.0; 2],
+
+            band_lo: 0.0,
+            band_hi: 0.0,
+            band_hp: [Biquad::new(), Biquad::new()],
+            band_lp: [Biquad::new(), Biquad::new()],
+            band_rest: stage_lines(44100.0),
+
+            harm_cut: 0.0,
+            harm_l: Biquad::new(),
+            harm_r: Biquad::new(),
+
+            spread: Allpass::new(spread_len(44100.0), SPREAD_G),
+
+            sat_in: Envelope::new(SAT_ATTACK, SAT_RELEASE, 1.0/44100.0),
+            sat_out: Envelope::new(SAT_ATTACK, SAT_RELEASE, 1.0/44100.0),
+            drive: 0.0,
+
+            eq_tilt: 0.0,
+            eq_gain: 1.0,
+            eq_pre: [Biquad::new(), Biquad::new()],
+            eq_post: [Biquad::new(), Biquad::new()],
+
+            dry_wet: Smoother::new(SMOOTH_TIME, 1.0/44100.0, Glide::Exponential),
+            pre_post: Smoother::new(GLIDE_TIME, 1.0/44100.0, Glide::Linear),
+            threshold: Smoother::new(GLIDE_TIME, 1.0/44100.0, Glide::Linear),
+            sq: Smoother::new(SMOOTH_TIME, 1.0/44100.0, Glide::Exponential),
+            coerc: Smoother::new(SMOOTH_TIME, 1.0/44100.0, Glide::Exponential),
+
+            coupling_by: Bypass::new(1.0/44100.0),
+            analog_by: Bypass::new(1.0/44100.0),
+            trap_by: Bypass::new(1.0/44100.0),
+            transient_by: Bypass::new(1.0/44100.0),
+            match_by: Bypass::new(1.0/44100.0),
+            wet_match_by: Bypass::new(1.0/44100.0),
+            safety_by: Bypass::new(1.0/44100.0),
+            hiss_lows_by: Bypass::new(1.0/44100.0),
+            level_by: Bypass::new(1.0/44100.0),
+            mat: [TapeMaterial::Iron, TapeMaterial::Iron],
+            mat_side: false,
+            mat_by: Bypass::new(1.0/44100.0),
+
+            tp_l: TruePeak::new(),
+            tp_r: TruePeak::new(),
+            peak_hold: Envelope::new(0.0, METER_RELEASE, 1.0/44100.0),
+            tp_hold: Envelope::new(0.0, METER_RELEASE, 1.0/44100.0),
+            meter_mode: MeterMode::Peak,
+
+            la: lookahead_lines(44100.0),
+            la_len: lookahead_len(44100.0),
+
+            os_l: Oversampler::new(),
+            os_r: Oversampler::new(),
+            os_stages: 0,
+            src_l: FixedRate::new(44100.0),
+            src_r: FixedRate::new(44100.0),
+            src_on: false,
+            recover_l: Recovery::new(1.0/44100.0),
+            recover_r: Recovery::new(1.0/44100.0),
+            os_dry: dry_lines(44100.0),
+        }
+    }
+}
+
+impl Drop for Effect {
+    fn drop(&mut self) {
+        if self.live {
+            LIVE.fetch_sub(1, Ordering::Relaxed);
         }
     }
 }
 
 impl Default for EffectParameters {
     fn default() -> EffectParameters {
-        EffectParameters {
-            pre_post: AtomicFloat::new(0.5),
-            dbg_sq: AtomicFloat::new(0.5),
-            dbg_coerc: AtomicFloat::new(0.5),
-            dry_wet: AtomicFloat::new(1.0),
-        }
+        let params = EffectParameters {
+            pre_post: AtomicFloat::new(DEFAULTS[0]),
+            dbg_sq: AtomicFloat::new(DEFAULTS[1]),
+            dbg_coerc: AtomicFloat::new(DEFAULTS[2]),
+            dry_wet: AtomicFloat::new(DEFAULTS[3]),
+            feedback: AtomicFloat::new(DEFAULTS[4]),
+            threshold: AtomicFloat::new(DEFAULTS[5]),
+            transient: AtomicFloat::new(DEFAULTS[6]),
+            ceiling: AtomicFloat::new(DEFAULTS[7]),
+            auto_level: AtomicFloat::new(DEFAULTS[8]),
+            analog_in: AtomicFloat::new(DEFAULTS[9]),
+            lookahead: AtomicFloat::new(DEFAULTS[10]),
+            bias_trap: AtomicFloat::new(DEFAULTS[11]),
+            loudness_match: AtomicFloat::new(DEFAULTS[12]),
+            splice_rate: AtomicFloat::new(DEFAULTS[13]),
+            splice_level: AtomicFloat::new(DEFAULTS[14]),
+            spread: AtomicFloat::new(DEFAULTS[15]),
+            mix_law: AtomicFloat::new(DEFAULTS[16]),
+            gap_loss: AtomicFloat::new(DEFAULTS[17]),
+            auto_drive: AtomicFloat::new(DEFAULTS[18]),
+            drive_target: AtomicFloat::new(DEFAULTS[19]),
+            loop_lock: AtomicFloat::new(DEFAULTS[20]),
+            clean_blend: AtomicFloat::new(DEFAULTS[21]),
+            dyn_coerc: AtomicFloat::new(DEFAULTS[22]),
+            dc_coupled: AtomicFloat::new(DEFAULTS[23]),
+            quant: AtomicFloat::new(DEFAULTS[24]),
+            head_cut: AtomicFloat::new(DEFAULTS[25]),
+            material: AtomicFloat::new(DEFAULTS[26]),
+            oversampling: AtomicFloat::new(DEFAULTS[27]),
+            feedback_tone: AtomicFloat::new(DEFAULTS[28]),
+            even: AtomicFloat::new(DEFAULTS[29]),
+            stereo_mode: AtomicFloat::new(DEFAULTS[30]),
+            width_depth: AtomicFloat::new(DEFAULTS[31]),
+            load_balance: AtomicFloat::new(DEFAULTS[32]),
+            drive_eq: AtomicFloat::new(DEFAULTS[33]),
+            medium: AtomicFloat::new(DEFAULTS[34]),
+            harm_hpf: AtomicFloat::new(DEFAULTS[35]),
+            thickness: AtomicFloat::new(DEFAULTS[36]),
+            fixed_rate: AtomicFloat::new(DEFAULTS[37]),
+            meter_mode: AtomicFloat::new(DEFAULTS[38]),
+            band_low: AtomicFloat::new(DEFAULTS[39]),
+            band_high: AtomicFloat::new(DEFAULTS[40]),
+            wow_depth: AtomicFloat::new(DEFAULTS[41]),
+            wow_rate: AtomicFloat::new(DEFAULTS[42]),
+            flutter_depth: AtomicFloat::new(DEFAULTS[43]),
+            flutter_rate: AtomicFloat::new(DEFAULTS[44]),
+            hiss: AtomicFloat::new(DEFAULTS[45]),
+            head_eq: AtomicFloat::new(DEFAULTS[46]),
+            tape_speed: AtomicFloat::new(DEFAULTS[47]),
+            link: AtomicFloat::new(DEFAULTS[48]),
+            bias: AtomicFloat::new(DEFAULTS[49]),
+            xover: AtomicFloat::new(DEFAULTS[50]),
+            dropout_rate: AtomicFloat::new(DEFAULTS[51]),
+            dropout_depth: AtomicFloat::new(DEFAULTS[52]),
+            wet_match: AtomicFloat::new(DEFAULTS[53]),
+            safety: AtomicFloat::new(DEFAULTS[54]),
+            safety_ceiling: AtomicFloat::new(DEFAULTS[55]),
+            rhythmic: AtomicFloat::new(DEFAULTS[56]),
+            rhythm_depth: AtomicFloat::new(DEFAULTS[57]),
+            hiss_mono_lows: AtomicFloat::new(DEFAULTS[58]),
+            instability: AtomicFloat::new(DEFAULTS[59]),
+            noise_gate: AtomicFloat::new(DEFAULTS[60]),
+            stereo_flutter: AtomicFloat::new(DEFAULTS[61]),
+
+            sample_peak: AtomicFloat::new(0.0),
+            true_peak: AtomicFloat::new(0.0),
+            saturation: AtomicFloat::new(0.0),
+            no_output: AtomicBool::new(false),
+
+            idle_floor: AtomicFloat::new(IDLE_FLOOR),
+
+            reset_pending: AtomicBool::new(false),
+            resync_pending: AtomicBool::new(false),
+
+            locks: AtomicU64::new(0),
+
+            slots: [empty_snapshot(), empty_snapshot()],
+            active_slot: AtomicUsize::new(0),
+
+            program: AtomicUsize::new(0),
+        };
+        // both slots start out holding the defaults
+        params.store_slot(Slot::A);
+        params.store_slot(Slot::B);
+        params
     }
 }
 
-// All plugins using `vst` also need to implement the `Plugin` trait.  Here, we
-// define functions that give necessary info to our host.
-impl Plugin for Effect {
-    fn get_info(&self) -> Info {
-        Info {
-            name: "HYSTERESIS".to_string(),
-            vendor: "Rust DSP".to_string(),
-            unique_id: 0x2d4e04e1,  // adler-32 of name + version (HYSTERESIS v0.3.x)
-            version: 31,
-            inputs: 2,
-            outputs: 2,
-            // This `parameters` bit is important; without it, none of our
-            // parameters will be shown!
-            parameters: 4,
-            category: Category::Effect,
-            initial_delay: 0,
-            ..Default::default()
+impl EffectParameters {
+    /// set every parameter back to its default value
+    /// + reset_state:  also clear the DSP state (hysteresis, filters,
+    ///   envelopes) at the start of the next block
+    pub fn reset_to_default(&self, reset_state: bool) {
+        for (i, &val) in DEFAULTS.iter().enumerate() {
+            self.store(i as i32, val);
+        }
+        if reset_state {
+            self.reset_pending.store(true, Ordering::Relaxed);
         }
     }
 
-    /*
-    fn set_sample_rate(&mut self, rate: f32){
-        self.sr = rate;
-        self.rate = 1.0/rate;
+    /// start wow, flutter and the vinyl rotation over at the start of the next
+    /// block, with the delays gliding into their new cycles. The hysteresis,
+    /// filters and envelopes keep their state, unlike reset_to_default.
+    pub fn reset_modulation(&self) {
+        self.resync_pending.store(true, Ordering::Relaxed);
     }
-    */
 
-    // Here is where the bulk of our audio processing code goes.
-    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
-        let (inputs, outputs) = buffer.split();
+    /// set a parameter regardless of its lock, every change from the plugin
+    /// itself goes through here
+    fn store(&self, index: i32, val: f32) {
+        // a misbehaving host could send NaN or values outside of 0 - 1,
+        // non-finite values are dropped so the last good value is kept
+        if !val.is_finite() {
+            return;
+        }
+        let val = val.clamp(0.0, 1.0);
+        #[allow(clippy::single_match)]
+        match index {
+            0 => self.pre_post.set(val),
+            1 => self.dbg_sq.set(val),
+            2 => self.dbg_coerc.set(val),
+            3 => self.dry_wet.set(val),
+            4 => self.feedback.set(val),
+            5 => self.threshold.set(val),
+            6 => self.transient.set(val),
+            7 => self.ceiling.set(val),
+            8 => self.auto_level.set(val),
+            9 => self.analog_in.set(val),
+            10 => self.lookahead.set(val),
+            11 => self.bias_trap.set(val),
+            12 => self.loudness_match.set(val),
+            13 => self.splice_rate.set(val),
+            14 => self.splice_level.set(val),
+            15 => self.spread.set(val),
+            16 => self.mix_law.set(val),
+            17 => self.gap_loss.set(val),
+            18 => self.auto_drive.set(val),
+            19 => self.drive_target.set(val),
+            20 => self.loop_lock.set(val),
+            21 => self.clean_blend.set(val),
+            22 => self.dyn_coerc.set(val),
+            23 => self.dc_coupled.set(val),
+            24 => self.quant.set(val),
+            25 => self.head_cut.set(val),
+            26 => self.material.set(val),
+            27 => self.oversampling.set(val),
+            28 => self.feedback_tone.set(val),
+            29 => self.even.set(val),
+            30 => self.stereo_mode.set(val),
+            31 => self.width_depth.set(val),
+            32 => self.load_balance.set(val),
+            33 => self.drive_eq.set(val),
+            34 => self.medium.set(val),
+            35 => self.harm_hpf.set(val),
+            36 => self.thickness.set(val),
+            37 => self.fixed_rate.set(val),
+            38 => self.meter_mode.set(val),
+            39 => self.band_low.set(val),
+            40 => self.band_high.set(val),
+            41 => self.wow_depth.set(val),
+            42 => self.wow_rate.set(val),
+            43 => self.flutter_depth.set(val),
+            44 => self.flutter_rate.set(val),
+            45 => self.hiss.set(val),
+            46 => self.head_eq.set(val),
+            47 => self.tape_speed.set(val),
+            48 => self.link.set(val),
+            49 => self.bias.set(val),
+            50 => self.xover.set(val),
+            51 => self.dropout_rate.set(val),
+            52 => self.dropout_depth.set(val),
+            53 => self.wet_match.set(val),
+            54 => self.safety.set(val),
+            55 => self.safety_ceiling.set(val),
+            56 => self.rhythmic.set(val),
+            57 => self.rhythm_depth.set(val),
+            58 => self.hiss_mono_lows.set(val),
+            59 => self.instability.set(val),
+            60 => self.noise_gate.set(val),
+            61 => self.stereo_flutter.set(val),
+            _ => (),
+        }
+    }
 
-        // Iterate over inputs as (&f32, &f32)
-        let (l, r) = inputs.split_at(1);
-        let stereo_in = l[0].iter().zip(r[0].iter());
+    /// lock a parameter, host automation can't change it until it is
+    /// unlocked. Presets, the A/B slots and the setters here still do.
+    /// + index:    parameter index, out of range ones are ignored
+    pub fn lock(&self, index: i32) {
+        if (0..NUM_PARAMS).contains(&index) {
+            self.locks.fetch_or(1 << index, Ordering::Relaxed);
+        }
+    }
+
+    /// unlock a parameter locked with lock
+    /// + index:    parameter index, out of range ones are ignored
+    pub fn unlock(&self, index: i32) {
+        if (0..NUM_PARAMS).contains(&index) {
+            self.locks.fetch_and(!(1 << index), Ordering::Relaxed);
+        }
+    }
 
-        // Iterate over outputs as (&mut f32, &mut f32)
-        let (mut l, mut r) = outputs.split_at_mut(1);
-        let stereo_out = l[0].iter_mut().zip(r[0].iter_mut());
+    /// whether a parameter is locked against host automation
+    pub fn is_locked(&self, index: i32) -> bool {
+        (0..NUM_PARAMS).contains(&index) && self.locks.load(Ordering::Relaxed) & (1 << index) != 0
+    }
 
-        // process
-        for ((left_in, right_in), (left_out, right_out)) in stereo_in.zip(stereo_out) {
+    /// local slope of the transfer curve (dOut/dIn) at a given input level,
+    /// for the current drive, squareness, coercitivity and material. Below
+    /// saturation the slope stays close to 1.0 and it drops as the curve
+    /// saturates.
+    /// + level:    input amplitude, linear
+    pub fn compression_ratio_at(&self, level: f32) -> f32 {
+        let pre_post = self.pre_post.get() as f64 * 24.0 - 12.0;
+        let thresh = self.threshold.get() as f64 * 36.0 - 36.0;
+        let pre  = db_to_gain( pre_post) * db_to_gain(-thresh);
+        let post = db_to_gain(-pre_post) * db_to_gain( thresh);
+        let sq = squareness(self.dbg_sq.get() as f64);
+        let c  = self.dbg_coerc.get() as f64;
 
-            // get params
-            let sq = self.params.dbg_sq.get() as f64;
-            let c  = self.params.dbg_coerc.get() as f64;
-            let pre_post = self.params.pre_post.get() as f64 * 24.0 - 12.0;
-            let pre  = db_to_gain( pre_post);
-            let post = db_to_gain(-pre_post);
-            let dry_wet = self.params.dry_wet.get() as f64;
+        let x1 = (level as f64).abs().max(1e-6);
+        let x2 = x1 * (1.0 + RATIO_DELTA);
+        let sat = compute::sat_for(self.material() as usize);
+        let y1 = sat(ramp_to(sq, c, x1 * pre)) * post;
+        let y2 = sat(ramp_to(sq, c, x2 * pre)) * post;
+        ((y2 - y1) / (x2 - x1)) as f32
+    }
 
-            // get inputs
-            let mut xl = *left_in as f64 * pre;
-            let mut xr = *right_in as f64 * pre;
+    /// area enclosed by the input/output loop of the model for the current
+    /// squareness and coercitivity, a measure of how much energy it loses per
+    /// cycle. Close to 0 for a near-linear curve.
+    pub fn loop_area(&self) -> f32 {
+        // shoelace formula over the measured cycle
+        let points = self.loop_points();
+        let area: f64 = points.windows(2).map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1).sum();
+        (area * 0.5).abs() as f32
+    }
 
-            // update process parameters
-            self.hyst_l.sq = sq;
-            self.hyst_r.sq = sq;
-            self.hyst_l.coerc = c;
-            self.hyst_r.coerc = c; 
+    /// input/output loop of the model for the current squareness and
+    /// coercitivity, for a GUI to draw. One cycle of input and output pairs,
+    /// starting at zero input on the way up.
+    pub fn loop_curve(&self) -> Vec<(f32, f32)> {
+        self.loop_points().iter().map(|&(x, y)| (x as f32, y as f32)).collect()
+    }
 
-            // execute process chains
-            xl = self.hyst_l.step(xl);
-            xr = self.hyst_r.step(xr);
+    /// one cycle of the loop, a slow triangle swings between -LOOP_PEAK and
+    /// LOOP_PEAK at the model input, the first cycle settles the state and
+    /// the second one is returned
+    fn loop_points(&self) -> Vec<(f64, f64)> {
+        let mut hyst = Hysteresis::new();
+        hyst.sq = squareness(self.dbg_sq.get() as f64);
+        hyst.coerc = self.dbg_coerc.get() as f64;
 
-            // === out =========================================================
-            *left_out  = x_fade(*left_in  as f64, dry_wet, xl * post) as f32;
-            *right_out = x_fade(*right_in as f64, dry_wet, xr * post) as f32;
+        let period = 4 * LOOP_STEPS;
+        let tri = |i: usize| {
+            let p = (i % period) as f64 / LOOP_STEPS as f64;
+            LOOP_PEAK * if p < 1.0 { p } else if p < 3.0 { 2.0 - p } else { p - 4.0 }
+        };
+        for i in 0..period {
+            hyst.step(tri(i));
         }
+        (period..=2 * period).map(|i| (tri(i), hyst.step(tri(i)))).collect()
     }
 
-    // Return the parameter object. This method can be omitted if the
-    // plugin has no parameters
-    fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
-        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+    /// whether the current settings pass the input through unchanged, so a
+    /// GUI can hint that the plugin is bypass-equivalent. That is the case
+    /// when the wet signal is below TRANSPARENT_TOL in the mix (-60 dB) and
+    /// nothing acting on the dry path is engaged: splice clicks, vinyl,
+    /// loudness match, auto level, the safety clip, clean blend, lookahead,
+    /// oversampling, the fixed rate and wow, flutter and instability (which
+    /// delay the output).
+    pub fn is_transparent(&self) -> bool {
+        self.dry_wet.get() < TRANSPARENT_TOL
+            && self.splice_rate.get() <= 0.0
+            && self.medium() == Medium::Tape
+            && self.loudness_match.get() <= 0.5
+            && self.auto_level.get() <= 0.5
+            && self.safety.get() <= 0.5
+            && self.lookahead.get() <= 0.5
+            && self.oversampling() == OversamplingFactor::X1
+            && self.fixed_rate.get() <= 0.5
+            && self.wow_depth.get() <= 0.0
+            && self.instability.get() <= 0.0
+            && self.flutter_depth.get() <= 0.0
+            && self.clean_blend.get() <= 0.0
     }
-}
 
-impl PluginParameters for EffectParameters {
-    // the `get_parameter` function reads the value of a parameter.
-    fn get_parameter(&self, index: i32) -> f32 {
-        match index {
-            0 => self.pre_post.get(),
-            1 => self.dbg_sq.get(),
-            2 => self.dbg_coerc.get(),
-            3 => self.dry_wet.get(),
-            _ => 0.0,
+    /// sample-peak level of the output, linear
+    pub fn sample_peak(&self) -> f32 {
+        self.sample_peak.get()
+    }
+
+    /// true-peak (4x oversampled) level of the output, linear
+    pub fn true_peak(&self) -> f32 {
+        self.true_peak.get()
+    }
+
+    /// encode all parameters into a short code that can be pasted as text
+    pub fn settings_code(&self) -> String {
+        code::encode(&self.values())
+    }
+
+    /// restore the parameters from a settings code, values are validated like
+    /// any host automation. Codes with fewer parameters (from older versions)
+    /// leave the remaining ones untouched.
+    /// Returns false, changing nothing, if the code is malformed.
+    pub fn apply_code(&self, code: &str) -> bool {
+        match code::decode(code) {
+            Some(values) => {
+                self.apply_values(&values);
+                true
+            }
+            None => false,
         }
     }
 
-    // the `set_parameter` function sets the value of a parameter.
-    fn set_parameter(&self, index: i32, val: f32) {
-        #[allow(clippy::single_match)]
-        match index {
-            0 => self.pre_post.set(val),
-            1 => self.dbg_sq.set(val),
-            2 => self.dbg_coerc.set(val),
-            3 => self.dry_wet.set(val),
-            _ => (),
+    /// all parameter values, in index order
+    fn values(&self) -> Vec<f32> {
+        (0..NUM_PARAMS).map(|i| self.get_parameter(i)).collect()
+    }
+
+    /// set the parameters from values in index order, extra values are
+    /// ignored and missing ones leave their parameters untouched
+    fn apply_values(&self, values: &[f32]) {
+        for (i, v) in values.iter().enumerate().take(NUM_PARAMS as usize) {
+            self.store(i as i32, *v);
         }
     }
 
-    // This is what will display underneath our control.  We can
-    // format it into a string that makes the most since.
-    fn get_parameter_text(&self, index: i32) -> String {
-        match index {
-            // 0 => format!("{:.2} dB", (self.pre_gain.get()*2.0).powf(2.0).log10()*20.0 ),
-            0 => format!("pre: {:.2} dB", 
-                self.pre_post.get() *  24.0 - 12.0),
-            1 => format!("{:.2}", self.dbg_sq.get()),
-            2 => format!("{:.2}", self.dbg_coerc.get()),
-            3 => format!("{:.1}% wet", self.dry_wet.get()*100.0),
-            _ => "".to_string(),
+    /// how hard the saturator is working, 0 is clean and 1 would be fully
+    /// saturated. Measured as the level lost between its input and output.
+    pub fn saturation(&self) -> f32 {
+        self.saturation.get()
+    }
+
+    /// whether the settings are silencing the plugin, so a GUI can flag it.
+    /// That is the case when the input is above -60 dB while the output peak
+    /// meter has fallen below -100 dB, e.g. full under-bias swallowing a
+    /// quiet, fully wet signal. It is measured rather than predicted from the
+    /// settings, so any combination that silences the output is caught.
+    pub fn no_output(&self) -> bool {
+        self.no_output.load(Ordering::Relaxed)
+    }
+
+    /// level in dBFS the input and output have to stay below for a while
+    /// before blocks of silence skip the processing, saving CPU on tracks
+    /// with a lot of silence. -inf turns the skipping off.
+    pub fn set_idle_floor(&self, db: f32) {
+        self.idle_floor.set(db);
+    }
+
+    pub fn idle_floor(&self) -> f32 {
+        self.idle_floor.get()
+    }
+
+    /// copy the current parameters into an A/B slot
+    pub fn store_slot(&self, slot: Slot) {
+        for (i, p) in self.slots[slot as usize].iter().enumerate() {
+            p.set(self.get_parameter(i as i32));
         }
     }
 
-    // This shows the control's name.
-    fn get_parameter_name(&self, index: i32) -> String {
-        match index {
-            0 => "pre/post gain",
-            1 => "squareness",
-            2 => "coercitivity",
-            3 => "dry/wet",
-            _ => "",
+    /// copy the parameters of an A/B slot into the live parameters
+    pub fn recall_slot(&self, slot: Slot) {
+        for (i, p) in self.slots[slot as usize].iter().enumerate() {
+            self.store(i as i32, p.get());
         }
-        .to_string()
+    }
+
+    /// switch the live parameters to another slot, the current values are
+    /// kept in the slot being left so edits aren't lost
+    pub fn switch_slot(&self, slot: Slot) {
+        let active = self.active_slot();
+        if active != slot {
+            self.store_slot(active);
+            self.recall_slot(slot);
+            self.active_slot.store(slot as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// slot the live parameters belong to
+    pub fn active_slot(&self) -> Slot {
+        if self.active_slot.load(Ordering::Relaxed) == 0 { Slot::A } else { Slot::B }
+    }
+
+    /// magnetic material of the saturation stage
+    pub fn material(&self) -> TapeMaterial {
+        TapeMaterial::from_param(self.material.get())
+    }
+
+    pub fn set_material(&self, material: TapeMaterial) {
+        self.store(26, material.to_param());
+    }
+
+    /// input coupling of the saturator
+    pub fn coupling(&self) -> Coupling {
+        Coupling::from_param(self.dc_coupled.get())
+    }
+
+    pub fn set_coupling(&self, coupling: Coupling) {
+        self.store(23, coupling.to_param());
+    }
+
+    /// dry/wet crossfade law
+    pub fn mix_law(&self) -> MixLaw {
+        MixLaw::from_param(self.mix_law.get())
+    }
+
+    pub fn set_mix_law(&self, law: MixLaw) {
+        self.store(16, law.to_param());
+    }
+
+    /// oversampling of the nonlinear stage
+    pub fn oversampling(&self) -> OversamplingFactor {
+        OversamplingFactor::from_param(self.oversampling.get())
+    }
+
+    pub fn set_oversampling(&self, factor: OversamplingFactor) {
+        self.store(27, factor.to_param());
+    }
+
+    /// how the two channels go through the hysteresis
+    pub fn stereo_mode(&self) -> StereoMode {
+        StereoMode::from_param(self.stereo_mode.get())
+    }
+
+    pub fn set_stereo_mode(&self, mode: StereoMode) {
+        self.store(30, mode.to_param());
+    }
+
+    /// character of the artifacts
+    pub fn medium(&self) -> Medium {
+        Medium::from_param(self.medium.get())
+    }
+
+    pub fn set_medium(&self, medium: Medium) {
+        self.store(34, medium.to_param());
+    }
+
+    /// tape speed of the head EQ
+    pub fn tape_speed(&self) -> TapeSpeed {
+        TapeSpeed::from_param(self.tape_speed.get())
+    }
+
+    pub fn set_tape_speed(&self, speed: TapeSpeed) {
+        self.store(47, speed.to_param());
+    }
+
+    /// ballistics of the sample-peak meter
+    pub fn meter_mode(&self) -> MeterMode {
+        MeterMode::from_param(self.meter_mode.get())
+    }
+
+    pub fn set_meter_mode(&self, mode: MeterMode) {
+        self.store(38, mode.to_param());
     }
 }
 
-// This part is important!  Without it, our plugin won't work.
-plugin_main!(Effect);
\ No newline at end of file
+/// drive a fresh hysteresis model with a slow rising ramp from silence to a
+/// target input, returns the output at the target
+fn ramp_to(sq: f64, coerc: f64, x: f64) -> f64 {
+    let mut hyst = Hysteresis::new();
+    hyst.sq = sq;
+    hyst.coerc = coerc;
+    let mut y = 0.0;
+    for i in 1..=RAMP_STEPS {
+        y = hyst.step(x * i as f64 / RAMP_STEPS as f64);
+    }
+    y
+}
+
+fn empty_snapshot() -> Vec<AtomicFloat> {
+    (0..NUM_PARAMS).map(|_| AtomicFloat::new(0.0)).collect()
+}
+
+impl Effect {
+    /// clear all processing state, parameters are left untouched
+    fn reset_state(&mut self) {
+        self.hyst_l = Hysteresis::new();
+        self.hyst_r = Hysteresis::new();
+        self.fb_l.reset();
+        self.fb_r.reset();
+        self.trans_fast.reset();
+        self.trans_slow.reset();
+        self.level_env.reset();
+        self.noise_gate.reset();
+        self.idle.reset();
+        self.dc_l.reset();
+        self.dc_r.reset();
+        self.even_l.reset();
+        self.even_r.reset();
+        self.bias.reset();
+        self.bias_l.reset();
+        self.bias_r.reset();
+        self.in_l.reset();
+        self.in_r.reset();
+        self.trap_l.reset();
+        self.trap_r.reset();
+        self.loud_in.reset();
+        self.loud_out.reset();
+        self.ms_dry.reset();
+        self.ms_wet.reset();
+        self.reseed();
+        self.vinyl.reset();
+        self.transport.reset();
+        self.hiss.reset();
+        self.dropout_l.reset();
+        self.dropout_r.reset();
+        self.quant_l = 0.0;
+        self.quant_r = 0.0;
+        self.head_l = 0.0;
+        self.head_r = 0.0;
+        self.head_cut.reset();
+        self.gap_l.reset();
+        self.gap_r.reset();
+        for f in self.thick_pre.iter_mut().chain(self.thick_post.iter_mut()) {
+            f.reset();
+        }
+        for f in self.emph_pre.iter_mut().chain(self.emph_post.iter_mut()).chain(self.bump.iter_mut()) {
+            f.reset();
+        }
+        self.roll = [0.0; 2];
+        for f in self.band_hp.iter_mut().chain(self.band_lp.iter_mut()) {
+            f.reset();
+        }
+        for line in self.band_rest.iter_mut() {
+            line.reset();
+        }
+        self.harm_l.reset();
+        self.harm_r.reset();
+        self.spread.reset();
+        self.sat_in.reset();
+        self.sat_out.reset();
+        self.drive = 0.0;
+        for f in self.eq_pre.iter_mut().chain(self.eq_post.iter_mut()) {
+            f.reset();
+        }
+        self.dry_wet.reset();
+        self.pre_post.reset();
+        self.threshold.reset();
+        self.sq.reset();
+        self.coerc.reset();
+        self.coupling_by.reset();
+        self.analog_by.reset();
+        self.trap_by.reset();
+        self.transient_by.reset();
+        self.match_by.reset();
+        self.wet_match_by.reset();
+        self.safety_by.reset();
+        self.hiss_lows_by.reset();
+        self.level_by.reset();
+        self.mat_by.reset();
+        self.tp_l.reset();
+        self.tp_r.reset();
+        self.peak_hold.reset();
+        self.tp_hold.reset();
+        for line in self.la.iter_mut() {
+            line.reset();
+        }
+        self.os_l.reset();
+        self.os_r.reset();
+        self.src_l.reset();
+        self.src_r.reset();
+        self.recover_l.reset();
+        self.recover_r.reset();
+        for line in self.os_dry.iter_mut() {
+            line.reset();
+        }
+    }
+
+    /// restart the random artifacts from the seed
+    fn reseed(&mut self) {
+        self.rng = Xoshiro256Plus::seed_from_u64(SEED);
+        self.splice.reset();
+        self.quant_rng_l = Xoshiro256Plus::seed_from_u64(self.seed);
+        self.quant_rng_r = Xoshiro256Plus::seed_from_u64(self.seed.wrapping_add(1));
+    }
+
+    /// follow the host transport. Starting playback clears the DSP state, so
+    /// whatever the last loud passage left in the model doesn't thump at the
+    /// start. With loop-lock on, the random artifacts are reseeded when the
+    /// transport jumps back (a loop wrapping around), so every pass of a
+    /// loop gets the same artifacts. While it plays, the rhythmic noise
+    /// follows its tempo.
+    /// + samples:  length of the current block
+    fn follow_transport(&mut self, samples: usize) {
+        // without a host, e.g. run from code, there is no transport, and the
+        // vst crate panics on any host call
+        if self.host.raw_callback().is_none() {
+            return;
+        }
+        let mask = TimeInfoFlags::TEMPO_VALID.bits() | TimeInfoFlags::PPQ_POS_VALID.bits();
+        if let Some(info) = self.host.get_time_info(mask) {
+            let has = |flag: TimeInfoFlags| info.flags & flag.bits() != 0;
+            let playing = has(TimeInfoFlags::TRANSPORT_PLAYING);
+            self.follow_position(playing, info.sample_pos, samples);
+            let tempo = playing && has(TimeInfoFlags::TEMPO_VALID) && has(TimeInfoFlags::PPQ_POS_VALID);
+            self.follow_beat(if tempo { Some((info.ppq_pos, info.tempo)) } else { None });
+        } else {
+            self.follow_beat(None);
+        }
+    }
+
+    /// the tempo handling of follow_transport, the noise falls back to a
+    /// steady level without one
+    /// + beat:     position of the block in beats and tempo in bpm
+    fn follow_beat(&mut self, beat: Option<(f64, f64)>) {
+        self.pulse.sync(beat, self.rate);
+        for bus in self.buses.iter_mut() {
+            bus.pulse.sync(beat, bus.rate);
+        }
+    }
+
+    /// the transport handling of follow_transport, for a known state
+    /// + playing:  whether the transport is running
+    /// + pos:      position of the block in samples
+    /// + samples:  length of the block
+    fn follow_position(&mut self, playing: bool, pos: f64, samples: usize) {
+        if playing && !self.playing {
+            self.reset_state();
+            for bus in self.buses.iter_mut() {
+                bus.reset_state();
+            }
+        } else if playing
+            && self.params.loop_lock.get() > 0.5
+            && pos + 0.5 < self.next_pos
+        {
+            self.reseed();
+            for bus in self.buses.iter_mut() {
+                bus.reseed();
+            }
+        }
+        self.playing = playing;
+        self.next_pos = if playing { pos + samples as f64 } else { 0.0 };
+    }
+
+    /// measured saturation, the level lost between the saturator input and
+    /// output envelopes, 0 - 1
+    fn saturation(&self) -> f64 {
+        let sat_in = self.sat_in.get();
+        if sat_in > SAT_FLOOR {
+            (1.0 - self.sat_out.get() / sat_in).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// latency introduced by the current settings, in samples
+    fn latency(&self) -> usize {
+        let la = if self.params.lookahead.get() > 0.5 { self.la_len } else { 0 };
+        let stage = if self.params.fixed_rate.get() > 0.5 {
+            self.src_l.latency()
+        } else {
+            oversample::latency(self.params.oversampling().stages())
+        };
+        la + stage + self.transport_latency()
+    }
+
+    /// latency of wow, flutter and instability, and of the wow of the vinyl
+    /// medium. Each swing is centered on a delay, which only applies while it
+    /// is on.
+    fn transport_latency(&self) -> usize {
+        let tape = if self.params.wow_depth.get() > 0.0
+            || self.params.flutter_depth.get() > 0.0
+            || self.params.instability.get() > 0.0
+        {
+            self.transport.latency()
+        } else {
+            0
+        };
+        let vinyl = if self.params.medium() == Medium::Vinyl { self.vinyl.latency() } else { 0 };
+        tape + vinyl
+    }
+
+    /// tell the host when the latency of the settings changed, so it reads
+    /// the initial delay again without reloading the plugin. Calling the host
+    /// isn't safe from the audio thread, so this waits for the host to resume
+    /// the plugin, which it does around most setting changes anyway.
+    fn report_latency(&mut self) {
+        let latency = self.latency();
+        if latency == self.reported_latency {
+            return;
+        }
+        self.reported_latency = latency;
+        let effect = self.host.raw_effect();
+        if let (Some(callback), false) = (self.host.raw_callback(), effect.is_null()) {
+            // the vst crate only fills in the initial delay when the plugin
+            // is loaded, and has no call for the IO change
+            unsafe { (*effect).initialDelay = latency as i32 };
+            callback(effect, IO_CHANGED, 0, 0, std::ptr::null_mut(), 0.0);
+        }
+    }
+
+    /// push a frame into the lookahead delay and return the delayed one
+    fn lookahead_step(&mut self, frame: [f64; 4]) -> [f64; 4] {
+        let mut out = [0.0; 4];
+        for ((line, x), y) in self.la.iter_mut().zip(frame.iter()).zip(out.iter_mut()) {
+            line.push(*x);
+            *y = line.read(self.la_len);
+        }
+        out
+    }
+
+    /// work done at the start of each block, before any sample is processed
+    /// + samples:  length of the block
+    fn begin_block(&mut self, samples: usize) {
+        if self.params.reset_pending.swap(false, Ordering::Relaxed) {
+            self.reset_state();
+            for bus in self.buses.iter_mut() {
+                bus.reset_state();
+            }
+        }
+        if self.params.resync_pending.swap(false, Ordering::Relaxed) {
+            self.transport.resync();
+            self.vinyl.resync();
+            for bus in self.buses.iter_mut() {
+                bus.transport.resync();
+                bus.vinyl.resync();
+            }
+        }
+        self.follow_transport(samples);
+        self.prepare_block();
+        for bus in self.buses.iter_mut() {
+            bus.prepare_block();
+        }
+    }
+
+    /// the part of begin_block every chain does for itself, the rest talks
+    /// to the host and is only done once per instance
+    fn prepare_block(&mut self) {
+        self.block = BlockParams::read(&self.params);
+        self.block.tp_len = self.transport_latency();
+        self.fb_l.set_cutoff(self.block.fb_cutoff, self.rate);
+        self.fb_r.set_cutoff(self.block.fb_cutoff, self.rate);
+        self.idle.set_floor(db_to_gain(self.params.idle_floor.get() as f64));
+        // the oversampling factor only changes between blocks, its filters
+        // start over so the old stages don't ring into the new ones
+        let stages = self.params.oversampling().stages();
+        if stages != self.os_stages {
+            self.os_stages = stages;
+            self.os_l.reset();
+            self.os_r.reset();
+        }
+        // so does the fixed rate mode
+        let src_on = self.params.fixed_rate.get() > 0.5;
+        if src_on != self.src_on {
+            self.src_on = src_on;
+            self.src_l.reset();
+            self.src_r.reset();
+        }
+        // load balancing, big sessions trade some aliasing for CPU
+        let economy = self.params.load_balance.get() > 0.5
+            && LIVE.load(Ordering::Relaxed) >= BALANCE_INSTANCES;
+        self.os_l.set_economy(economy);
+        self.os_r.set_economy(economy);
+        let tilt = self.params.drive_eq.get() as f64 * 2.0 * DRIVE_EQ_MAX - DRIVE_EQ_MAX;
+        if tilt != self.eq_tilt {
+            self.set_drive_eq(tilt);
+        }
+        let meter_mode = self.params.meter_mode();
+        if meter_mode != self.meter_mode {
+            self.set_meter_mode(meter_mode);
+        }
+        let thick = self.params.thickness.get() as f64 * THICK_MAX;
+        if thick != self.thick_gain {
+            self.set_thickness(thick);
+        }
+        let speed = if self.params.head_eq.get() > 0.5 { Some(self.params.tape_speed()) } else { None };
+        if speed != self.head_speed {
+            self.set_head_eq(speed);
+        }
+        let lo = band_edge(self.params.band_low.get() as f64, 0.0);
+        let hi = band_edge(self.params.band_high.get() as f64, 1.0);
+        if lo != self.band_lo || hi != self.band_hi {
+            self.set_band(lo, hi);
+        }
+        let cut = harm_hpf(self.params.harm_hpf.get() as f64);
+        if cut != self.harm_cut {
+            self.set_harm_hpf(cut);
+        }
+    }
+
+    /// set the ballistics of the sample-peak meter
+    fn set_meter_mode(&mut self, mode: MeterMode) {
+        self.meter_mode = mode;
+        let (attack, release) = mode.times();
+        self.peak_hold.set_times(attack, release, self.rate);
+    }
+
+    /// set the thickness filters
+    /// + gain:     low-mid boost into the saturator in dB
+    fn set_thickness(&mut self, gain: f64) {
+        self.thick_gain = gain;
+        let freq = THICK_FREQ.min(self.sr * 0.45);
+        for f in self.thick_pre.iter_mut() {
+            f.set_peaking(freq, THICK_Q, gain, self.rate);
+        }
+        for f in self.thick_post.iter_mut() {
+            f.set_peaking(freq, THICK_Q, -gain, self.rate);
+        }
+    }
+
+    /// set the head EQ filters
+    /// + speed:    tape speed, None turns the EQ off
+    fn set_head_eq(&mut self, speed: Option<TapeSpeed>) {
+        self.head_speed = speed;
+        if let Some(speed) = speed {
+            let corner = speed.emphasis().min(self.sr * 0.45);
+            for (pre, post) in self.emph_pre.iter_mut().zip(self.emph_post.iter_mut()) {
+                pre.set_high_shelf(corner, 0.707, EMPHASIS_GAIN, self.rate);
+                post.set_high_shelf(corner, 0.707, -EMPHASIS_GAIN, self.rate);
+            }
+            for f in self.bump.iter_mut() {
+                f.set_peaking(speed.bump(), BUMP_Q, BUMP_GAIN, self.rate);
+            }
+        }
+    }
+
+    /// set the saturation band filters
+    /// + lo:   low edge in Hz, 0 is off
+    /// + hi:   high edge in Hz, 0 is off
+    fn set_band(&mut self, lo: f64, hi: f64) {
+        self.band_lo = lo;
+        self.band_hi = hi;
+        for f in self.band_hp.iter_mut() {
+            if lo > 0.0 {
+                f.set_highpass(lo, 0.707, self.rate);
+            }
+        }
+        for f in self.band_lp.iter_mut() {
+            if hi > 0.0 {
+                f.set_lowpass(hi, 0.707, self.rate);
+            }
+        }
+    }
+
+    /// set the harmonic high-pass filters
+    /// + cutoff:   Hz, 0 is off
+    fn set_harm_hpf(&mut self, cutoff: f64) {
+        self.harm_cut = cutoff;
+        if cutoff > 0.0 {
+            for f in [&mut self.harm_l, &mut self.harm_r] {
+                f.set_highpass(cutoff, 0.707, self.rate);
+            }
+        }
+    }
+
+    /// set the drive EQ shelves, half the tilt goes up on one side of the
+    /// midpoint and half down on the other, and the shelves after the
+    /// saturator undo it exactly
+    /// + tilt:     dB, positive makes the lows drive harder
+    fn set_drive_eq(&mut self, tilt: f64) {
+        self.eq_tilt = tilt;
+        self.eq_gain = db_to_gain(0.5 * tilt);
+        let freq = DRIVE_EQ_FREQ.min(self.sr * 0.45);
+        for (pre, post) in self.eq_pre.iter_mut().zip(self.eq_post.iter_mut()) {
+            pre.set_high_shelf(freq, 0.707, -tilt, self.rate);
+            post.set_high_shelf(freq, 0.707, tilt, self.rate);
+        }
+    }
+
+    /// hand the meter readings over to the parameter object
+    fn publish_meters(&self) {
+        // on a multichannel bus the meters take the highest of all chains
+        let chains = || std::iter::once(self).chain(self.buses.iter());
+        let highest = |f: fn(&Effect) -> f64| chains().map(f).fold(0.0, f64::max);
+        let peak = highest(|c| c.peak_hold.get());
+        self.params.sample_peak.set(peak as f32);
+        self.params.true_peak.set(highest(|c| c.tp_hold.get()) as f32);
+        self.params.saturation.set(highest(Effect::saturation) as f32);
+        let silenced = highest(|c| c.trans_slow.get()) > SIGNAL_FLOOR && peak < SILENCE_FLOOR;
+        self.params.no_output.store(silenced, Ordering::Relaxed);
+    }
+
+    /// run the chain over any source of stereo samples, for offline use. It
+    /// is processed in blocks like the host would pass them, each one
+    /// starting with the parameter reads and ending with the meters, and the
+    /// last one ends with the source. The state carries over from and into
+    /// the next call like it does between blocks.
+    /// + it:       stereo samples
+    /// + block:    block length in samples
+    pub fn process_iter<'a, I>(&'a mut self, mut it: I, block: usize) -> impl Iterator<Item = (f32, f32)> + 'a
+    where
+        I: Iterator<Item = (f32, f32)> + 'a,
+    {
+        let block = block.max(1);
+        let mut n = 0;
+        std::iter::from_fn(move || match it.next() {
+            Some((l, r)) => {
+                if n == 0 {
+                    self.begin_block(block);
+                }
+                let (yl, yr) = self.process_sample(l as f64, r as f64);
+                n += 1;
+                if n == block {
+                    self.publish_meters();
+                    n = 0;
+                }
+                Some((yl as f32, yr as f32))
+            }
+            None => {
+                if n > 0 {
+                    self.publish_meters();
+                    n = 0;
+                }
+                None
+            }
+        })
+    }
+
+    /// delay and gain that null the output against the dry input for the
+    /// current settings, so that the output is close to gain times the input
+    /// delayed by that many samples. A fresh copy of the chain runs a burst
+    /// of noise at the same rate and the delay is the peak of the cross
+    /// correlation of its left channel. Meant for near-linear settings, for
+    /// others the gain is only the best fit.
+    pub fn null_calibration(&self) -> (usize, f64) {
+        let mut probe = Effect::chain(Arc::new(EffectParameters::default()), false);
+        probe.params.apply_values(&self.params.values());
+        probe.set_sample_rate(self.sr as f32);
+        probe.prepare_block();
+
+        let mut rng = Xoshiro256Plus::seed_from_u64(SEED);
+        let x: Vec<f64> = (0..CAL_LEN)
+            .map(|_| CAL_LEVEL * (2.0 * artifacts::uniform(&mut rng) - 1.0))
+            .collect();
+        let y: Vec<f64> = x.iter().map(|&s| probe.process_sample(s, s).0).collect();
+
+        // correlation and input energy over the part where every lag overlaps
+        let fit = |lag: usize| {
+            let (mut xy, mut xx) = (0.0, 0.0);
+            for n in CAL_LAG..CAL_LEN {
+                xy += y[n] * x[n - lag];
+                xx += x[n - lag] * x[n - lag];
+            }
+            (xy, xx)
+        };
+        let mut best = (0, 0.0);
+        for lag in 0..CAL_LAG {
+            let (xy, xx) = fit(lag);
+            let score = xy.abs() / xx.sqrt().max(f64::MIN_POSITIVE);
+            if score > best.1 {
+                best = (lag, score);
+            }
+        }
+        let (xy, xx) = fit(best.0);
+        (best.0, if xx > 0.0 { xy / xx } else { 0.0 })
+    }
+
+    /// process one stereo sample through the whole chain
+    fn process_sample(&mut self, left_in: f64, right_in: f64) -> (f64, f64) {
+        // a NaN or inf from the host would otherwise end up in the state of
+        // every stage
+        let left_in = sanitize(left_in);
+        let right_in = sanitize(right_in);
+
+        // get params, read once per block
+        let b = self.block;
+        // the model parameters are smoothed, stepping them is audible as
+        // zipper noise
+        self.sq.set_target(b.sq);
+        self.coerc.set_target(b.coerc);
+        let sq = self.sq.next();
+        let c  = self.coerc.next();
+        // gains are smoothed in dB, so they glide linearly in dB
+        self.pre_post.set_target(b.pre_post);
+        let pre_post = self.pre_post.next();
+        let pre  = db_to_gain( pre_post);
+        let post = db_to_gain(-pre_post);
+        // the threshold scales the signal into the model so that audible
+        // saturation begins at that level, and makes up for it after
+        self.threshold.set_target(b.threshold);
+        let thresh = self.threshold.next();
+        let pre  = pre  * db_to_gain(-thresh);
+        let post = post * db_to_gain( thresh);
+        self.dry_wet.set_target(b.dry_wet);
+        let dry_wet = self.dry_wet.next();
+        let equal_power = b.equal_power;
+        let fb = b.fb;
+        let transient = self.transient_by.step(b.transient);
+        let ceiling = b.ceiling;
+        let auto_level = self.level_by.step(b.auto_level);
+        let analog_in = self.analog_by.step(b.analog_in);
+        let lookahead = b.lookahead;
+        let trap = self.trap_by.step(b.trap);
+        let loudness_match = self.match_by.step(b.loudness_match);
+        let wet_match = self.wet_match_by.step(b.wet_match);
+        let safety = self.safety_by.step(b.safety);
+        let safety_ceiling = b.safety_ceiling;
+        let splice_rate = b.splice_rate;
+        let splice_level = b.splice_level;
+        let hiss = b.hiss * self.pulse.step(b.rhythm);
+        let hiss_mono_lows = self.hiss_lows_by.step(b.hiss_mono_lows);
+        let dropout_rate = b.dropout_rate;
+        let dropout_depth = b.dropout_depth;
+        let spread = b.spread;
+        let gap = b.gap;
+        let clean_blend = b.clean_blend;
+        let dyn_coerc = b.dyn_coerc;
+        let ac_coupled = self.coupling_by.step(b.ac_coupled);
+        let auto_drive = b.auto_drive;
+        let drive_target = b.drive_target;
+        // a single channel runs through one model
+        let stereo_mode = if self.mono { StereoMode::Linked } else { b.stereo_mode };
+        let link = b.link;
+        self.bias.set_target(b.bias);
+        let bias = self.bias.next();
+        let xover = b.xover;
+        let width_depth = b.width_depth;
+        let vinyl = b.vinyl;
+        let even = b.even;
+        let quant = b.quant;
+        // a new material goes on the side the crossfade isn't heading to, and
+        // the crossfade turns around towards it
+        let material = b.material;
+        if material != self.mat[self.mat_side as usize] {
+            self.mat_side = !self.mat_side;
+            self.mat[self.mat_side as usize] = material;
+        }
+        let t = self.mat_by.step(self.mat_side);
+        let sat_a = compute::sat_for(self.mat[0] as usize);
+        let sat_b = compute::sat_for(self.mat[1] as usize);
+        let sat = move |y: f64| {
+            if t <= 0.0 {
+                sat_a(y)
+            } else if t >= 1.0 {
+                sat_b(y)
+            } else {
+                x_fade(sat_a(y), t, sat_b(y))
+            }
+        };
+
+        // auto drive, from the saturation measured up to the previous sample
+        let pre  = pre  * db_to_gain( self.drive);
+        let post = post * db_to_gain(-self.drive);
+
+        // input coupling, AC blocks DC before it reaches the asymmetric parts
+        // of the chain, DC lets offsets through to the saturator
+        let cl = x_fade(left_in,  ac_coupled, left_in  - self.dc_l.step(left_in));
+        let cr = x_fade(right_in, ac_coupled, right_in - self.dc_r.step(right_in));
+
+        // analog input stage, a gentle loading roll-off before the saturator
+        let il = self.in_l.step(cl);
+        let ir = self.in_r.step(cr);
+        let wl = x_fade(cl, analog_in, il);
+        let wr = x_fade(cr, analog_in, ir);
+
+        // get inputs, with the filtered wet signal of the previous
+        // sample fed back into the saturator
+        let mut xl = (wl * pre + self.fb_l.get() * fb).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+        let mut xr = (wr * pre + self.fb_r.get() * fb).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+
+        // thickness, the low-mids go into the saturator hotter
+        let thick = self.thick_gain > 0.0;
+        if thick {
+            xl = self.thick_pre[0].step(xl).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+            xr = self.thick_pre[1].step(xr).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+        }
+
+        // record pre-emphasis, the highs go onto the tape hotter
+        if self.head_speed.is_some() {
+            xl = self.emph_pre[0].step(xl).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+            xr = self.emph_pre[1].step(xr).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+        }
+
+        // saturation band, only the band goes on into the saturator and the
+        // rest goes around it
+        let band = self.band_lo > 0.0 || self.band_hi > 0.0;
+        let (mut rest_l, mut rest_r) = (0.0, 0.0);
+        if band {
+            let mut bl = xl;
+            let mut br = xr;
+            if self.band_lo > 0.0 {
+                bl = self.band_hp[0].step(bl);
+                br = self.band_hp[1].step(br);
+            }
+            if self.band_hi > 0.0 {
+                bl = self.band_lp[0].step(bl);
+                br = self.band_lp[1].step(br);
+            }
+            rest_l = xl - bl;
+            rest_r = xr - br;
+            xl = bl;
+            xr = br;
+        }
+
+        // drive EQ, the signal goes into the saturator tilted so some
+        // frequencies drive it harder than others
+        let tilted = self.eq_tilt != 0.0;
+        if tilted {
+            xl = (self.eq_pre[0].step(xl) * self.eq_gain).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+            xr = (self.eq_pre[1].step(xr) * self.eq_gain).clamp(-SAT_IN_LIMIT, SAT_IN_LIMIT);
+        }
+
+        // dynamic coercitivity, louder signals push the coercitivity up
+        // towards 1, the level is squashed into 0 - 1 so it stays in range.
+        // It is the level going into the saturator, after the drive EQ.
+        let level = self.sat_in.step(xl.abs().max(xr.abs()));
+        let c = (c + dyn_coerc * (1.0 - c) * level / (1.0 + level)).clamp(0.0, 1.0);
+
+        // update process parameters
+        self.hyst_l.sq = sq;
+        self.hyst_r.sq = sq;
+        self.hyst_l.coerc = c;
+        self.hyst_r.coerc = c; 
+
+        // under-bias, too little bias leaves a dead zone around zero like a
+        // crossover stage, and the bias shifts the operating point of the
+        // magnetization off center, adding even harmonics
+        if xover > 0.0 {
+            xl = compute::digital_xover(xl, XOVER_WIDTH, xover);
+            xr = compute::digital_xover(xr, XOVER_WIDTH, xover);
+        }
+        let biased = bias > 1e-6;
+        if biased {
+            xl += bias;
+            xr += bias;
+        }
+
+        // execute process chains, the hysteresis and the material curve
+        // shaping the magnetization are oversampled together
+        let stages = self.os_stages;
+        let (src_l, src_r) = if self.src_on {
+            (Some(&mut self.src_l), Some(&mut self.src_r))
+        } else {
+            (None, None)
+        };
+        match stereo_mode {
+            StereoMode::Independent if link > 0.0 => {
+                // each model runs on its channel pulled towards the average,
+                // and its distortion is added to the channel, all the way
+                // linked this is the same as the linked mode
+                let m = 0.5 * (xl + xr);
+                let il = x_fade(xl, link, m);
+                let ir = x_fade(xr, link, m);
+                let dl = saturate(&mut self.os_l, src_l, &mut self.hyst_l, &mut self.recover_l, il, stages, sat) - il;
+                let dr = saturate(&mut self.os_r, src_r, &mut self.hyst_r, &mut self.recover_r, ir, stages, sat) - ir;
+                xl = (xl + dl).clamp(-SAT_OUT_LIMIT, SAT_OUT_LIMIT);
+                xr = (xr + dr).clamp(-SAT_OUT_LIMIT, SAT_OUT_LIMIT);
+            }
+            StereoMode::Independent => {
+                xl = saturate(&mut self.os_l, src_l, &mut self.hyst_l, &mut self.recover_l, xl, stages, sat);
+                xr = saturate(&mut self.os_r, src_r, &mut self.hyst_r, &mut self.recover_r, xr, stages, sat);
+            }
+            StereoMode::Linked => {
+                let m = 0.5 * (xl + xr);
+                let d = saturate(&mut self.os_l, src_l, &mut self.hyst_l, &mut self.recover_l, m, stages, sat) - m;
+                xl = (xl + d).clamp(-SAT_OUT_LIMIT, SAT_OUT_LIMIT);
+                xr = (xr + d).clamp(-SAT_OUT_LIMIT, SAT_OUT_LIMIT);
+            }
+            StereoMode::MidSide => {
+                let m = FRAC_1_SQRT_2 * (xl + xr);
+                let s = FRAC_1_SQRT_2 * (xl - xr);
+                let m = saturate(&mut self.os_l, src_l, &mut self.hyst_l, &mut self.recover_l, m, stages, sat);
+                let s = saturate(&mut self.os_r, src_r, &mut self.hyst_r, &mut self.recover_r, s, stages, sat);
+                xl = FRAC_1_SQRT_2 * (m + s);
+                xr = FRAC_1_SQRT_2 * (m - s);
+            }
+        }
+
+        // the DC the bias shift causes is taken off again
+        if biased {
+            xl -= self.bias_l.step(xl);
+            xr -= self.bias_r.step(xr);
+        }
+        self.sat_out.step(xl.abs().max(xr.abs()));
+
+        // and the drive EQ tilt comes off
+        if tilted {
+            xl = self.eq_post[0].step(xl) / self.eq_gain;
+            xr = self.eq_post[1].step(xr) / self.eq_gain;
+        }
+
+        // the rest of the spectrum comes back in, delayed like the band
+        let stage_len = if self.src_on { self.src_l.latency() } else { oversample::latency(stages) };
+        if band {
+            self.band_rest[0].push(rest_l);
+            self.band_rest[1].push(rest_r);
+            xl += self.band_rest[0].read(stage_len);
+            xr += self.band_rest[1].read(stage_len);
+        }
+
+        // and come out cut by as much, so quiet signals stay flat while the
+        // low-mids saturate harder
+        if thick {
+            xl = self.thick_post[0].step(xl);
+            xr = self.thick_post[1].step(xr);
+        }
+
+        // playback de-emphasis, the head bump and the roll-off of the head,
+        // all following the tape speed
+        if let Some(speed) = self.head_speed {
+            let cutoff = speed.rolloff();
+            let yl = self.bump[0].step(self.emph_post[0].step(xl));
+            let yr = self.bump[1].step(self.emph_post[1].step(xr));
+            self.roll[0] = flush(compute::play(yl, self.roll[0], cutoff, self.rate));
+            self.roll[1] = flush(compute::play(yr, self.roll[1], cutoff, self.rate));
+            xl = self.roll[0];
+            xr = self.roll[1];
+        }
+
+        // the dry signal, delayed to line up with the oversampled wet one and
+        // the center of the wow and flutter
+        let tp_len = b.tp_len;
+        self.os_dry[0].push(left_in);
+        self.os_dry[1].push(right_in);
+        let dry_l = self.os_dry[0].read(stage_len + tp_len);
+        let dry_r = self.os_dry[1].read(stage_len + tp_len);
+
+        // even harmonics, the full-wave rectified and shaped signal only holds
+        // even harmonics and DC, the DC is taken off and the sum is scaled
+        // back so the peaks stay about where they were
+        if even > 0.0 {
+            let el = compute::mag_sat_2(xl.abs());
+            let er = compute::mag_sat_2(xr.abs());
+            let el = el - self.even_l.step(el);
+            let er = er - self.even_r.step(er);
+            xl = (xl + even * el) / (1.0 + 0.5 * even);
+            xr = (xr + even * er) / (1.0 + 0.5 * even);
+        }
+
+        // stochastic quantization of the magnetization, and the playback head
+        if quant > 0.0 {
+            self.quant_l = compute::x_quant(xl, self.quant_l, self.rate, quant, &mut self.quant_rng_l);
+            self.quant_r = compute::x_quant(xr, self.quant_r, self.rate, quant, &mut self.quant_rng_r);
+            xl = self.quant_l;
+            xr = self.quant_r;
+        }
+        // the cutoff glides so automating it doesn't zipper, and snaps to
+        // where the filter is switched on
+        if let Some(cutoff) = b.head_cut {
+            self.head_cut.set_target(cutoff);
+            let cutoff = self.head_cut.next();
+            self.head_l = flush(compute::play(xl, self.head_l, cutoff, self.rate));
+            self.head_r = flush(compute::play(xr, self.head_r, cutoff, self.rate));
+            xl = self.head_l;
+            xr = self.head_r;
+        } else {
+            self.head_cut.reset();
+        }
+
+        // saturation meter, and auto drive slowly riding the input gain so
+        // the measured saturation settles on the target. Silence holds the
+        // drive where it is, turning the mode off glides it back to 0 dB.
+        let speed = DRIVE_SPEED * self.rate;
+        if auto_drive {
+            if self.sat_in.get() > SAT_FLOOR {
+                self.drive += speed * (drive_target - self.saturation());
+            }
+        } else {
+            self.drive -= self.drive.clamp(-speed, speed);
+        }
+        self.drive = self.drive.clamp(-DRIVE_MAX, DRIVE_MAX);
+
+        // update feedback path, a non-finite value would otherwise
+        // circulate forever, so the path is cleared instead
+        fb_update(&mut self.fb_l, xl);
+        fb_update(&mut self.fb_r, xr);
+
+        // bias trap, runs all the time so the filter is settled when enabled
+        let tl = self.trap_l.step(xl);
+        let tr = self.trap_r.step(xr);
+        xl = x_fade(xl, trap, tl);
+        xr = x_fade(xr, trap, tr);
+
+        // transient preserve, leans towards dry on attacks
+        let peak = left_in.abs().max(right_in.abs());
+        let fast = self.trans_fast.step(peak);
+        let slow = self.trans_slow.step(peak);
+        let dry_wet = if transient > 0.0 && fast > 1e-9 {
+            dry_wet * (1.0 - transient * ((fast - slow) / fast).clamp(0.0, 1.0))
+        } else {
+            dry_wet
+        };
+
+        // splice clicks sit on the tape, so they hit both channels
+        let mut click = self.splice.step(splice_rate, splice_level, &mut self.rng);
+
+        // head gap loss, the window is the time the tape takes to cross the
+        // gap, without a gap it is held at one sample so it passes through
+        let gap_window = gap * self.sr;
+        xl = self.gap_l.step(xl, gap_window);
+        xr = self.gap_r.step(xr, gap_window);
+
+        // dropouts, the channels lose contact with the head on their own
+        if dropout_rate > 0.0 {
+            xl = self.dropout_l.step(xl, dropout_rate, dropout_depth, &mut self.rng);
+            xr = self.dropout_r.step(xr, dropout_rate, dropout_depth, &mut self.rng);
+        }
+
+        // vinyl, the record adds its scratch and rumble to the clicks and
+        // wobbles in pitch
+        if vinyl {
+            click += self.vinyl.step(splice_level, &mut self.rng);
+            let (wl, wr) = self.vinyl.wow(xl, xr);
+            xl = wl;
+            xr = wr;
+        }
+
+        // wow and flutter
+        if b.wow.0 > 0.0 || b.flutter.0 > 0.0 || b.instability > 0.0 {
+            let (wl, wr) = self.transport.step((xl, xr), b.wow, b.flutter, b.instability, b.stereo_flutter, &mut self.rng);
+            xl = wl;
+            xr = wr;
+        }
+
+        // the wet signal, made up for the input gain
+        let (mut wet_l, mut wet_r) = (xl * post, xr * post);
+
+        // harmonic high-pass, only the difference the processing makes is
+        // filtered, so the original sub-bass passes untouched
+        if self.harm_cut > 0.0 {
+            wet_l = dry_l + self.harm_l.step(wet_l - dry_l);
+            wet_r = dry_r + self.harm_r.step(wet_r - dry_r);
+        }
+
+        // harmonic spread, the harmonics (wet minus dry) get a decorrelated
+        // side component while the fundamental stays where it was
+        let harm = 0.5 * ((wet_l - dry_l) + (wet_r - dry_r));
+        let side = spread * self.spread.step(harm);
+        wet_l += side;
+        wet_r -= side;
+
+        // drive-linked width, the harder the tape is driven the more the
+        // distortion products common to both channels narrow the image
+        if width_depth > 0.0 {
+            let drive = pre_post - thresh + self.drive;
+            let width = 1.0 - width_depth * WIDTH_NARROW * (drive / WIDTH_DRIVE).clamp(0.0, 1.0);
+            let (m, s) = (0.5 * (wet_l + wet_r), 0.5 * (wet_l - wet_r) * width);
+            wet_l = m + s;
+            wet_r = m - s;
+        }
+
+        // wet gain match, makes up the level the saturation took or added so
+        // the wet signal is as loud as the dry one going into the mix, the
+        // estimators only run while the mode is on
+        if wet_match > 0.0 {
+            let ms_dry = self.ms_dry.step(dry_l, dry_r);
+            let ms_wet = self.ms_wet.step(wet_l, wet_r);
+            let trim = if ms_wet > 1e-12 { (ms_dry / ms_wet).sqrt() } else { 1.0 };
+            let trim = x_fade(1.0, wet_match, trim.clamp(1.0 / MATCH_MAX, MATCH_MAX));
+            wet_l *= trim;
+            wet_r *= trim;
+        }
+
+        // tape noise, the asperity part follows what is on the tape, and
+        // the gate fades it out in the pauses of the input. The noise runs on
+        // under a closed gate, so the random sequence stays the same.
+        let (hiss_l, hiss_r) = if hiss > 0.0 {
+            let (l, r) = self.hiss.step(hiss, (wet_l, wet_r), hiss_mono_lows, &mut self.rng);
+            let gate = match b.noise_gate {
+                Some(threshold) => self.noise_gate.step(dry_l.abs().max(dry_r.abs()), threshold),
+                None => 1.0,
+            };
+            (gate * l, gate * r)
+        } else {
+            (0.0, 0.0)
+        };
+
+        // === out =========================================================
+        let (wet_l, wet_r) = (wet_l + click + hiss_l, wet_r + click + hiss_r);
+        let ml = mix(dry_l, dry_wet, wet_l, equal_power);
+        let mr = mix(dry_r, dry_wet, wet_r, equal_power);
+
+        // with lookahead the mix is delayed, while the detectors keep
+        // running on the undelayed signal and can react ahead of transients
+        let (mut yl, mut yr, clean_l, clean_r) = if lookahead {
+            let d = self.lookahead_step([dry_l, dry_r, wet_l, wet_r]);
+            (mix(d[0], dry_wet, d[2], equal_power), mix(d[1], dry_wet, d[3], equal_power), d[0], d[1])
+        } else {
+            (ml, mr, dry_l, dry_r)
+        };
+
+        // loudness match, trims the output so its short-term loudness follows
+        // the input, the estimators only run while the mode is on
+        if loudness_match > 0.0 {
+            let ms_in  = self.loud_in.step(left_in, right_in);
+            let ms_out = self.loud_out.step(yl, yr);
+            let trim = if ms_out > 1e-12 { (ms_in / ms_out).sqrt() } else { 1.0 };
+            let trim = x_fade(1.0, loudness_match, trim.clamp(1.0 / MATCH_MAX, MATCH_MAX));
+            yl *= trim;
+            yr *= trim;
+        }
+
+        // auto level, makes the peaks ride up to the ceiling and soft-clips
+        // whatever overshoots the detector
+        if auto_level > 0.0 {
+            let peak = self.level_env.step(ml.abs().max(mr.abs()));
+            let gain = (ceiling / peak.max(1e-9)).clamp(1.0 / AUTO_MAX, AUTO_MAX);
+            yl = x_fade(yl, auto_level, compute::soft_clip(yl * gain, ceiling));
+            yr = x_fade(yr, auto_level, compute::soft_clip(yr * gain, ceiling));
+        }
+
+        // clean blend, the input aligned to the output latency and summed on
+        // top of everything else, untouched by the output stages
+        let yl = (yl + clean_blend * clean_l).clamp(-OUT_LIMIT, OUT_LIMIT);
+        let yr = (yr + clean_blend * clean_r).clamp(-OUT_LIMIT, OUT_LIMIT);
+
+        // safety clip, the last stage, keeps the output under its own ceiling
+        // whatever the settings before it do
+        let (yl, yr) = if safety > 0.0 {
+            (x_fade(yl, safety, compute::soft_clip(yl, safety_ceiling)),
+             x_fade(yr, safety, compute::soft_clip(yr, safety_ceiling)))
+        } else {
+            (yl, yr)
+        };
+
+        // meters
+        self.peak_hold.step(yl.abs().max(yr.abs()));
+        self.tp_hold.step(self.tp_l.step(yl).max(self.tp_r.step(yr)));
+
+        (yl, yr)
+    }
+}
+
+// All plugins using `vst` also need to implement the `Plugin` trait.  Here, we
+// define functions that give necessary info to our host.
+impl Plugin for Effect {
+    fn new(host: HostCallback) -> Self {
+        let instance = INSTANCES.fetch_add(1, Ordering::Relaxed);
+        let mut effect = Effect::default();
+        effect.host = host;
+        effect.seed = SEED.wrapping_add(instance.wrapping_mul(2));
+        effect.reseed();
+        effect
+    }
+
+    fn resume(&mut self) {
+        self.report_latency();
+        // the chains for the other pairs of a multichannel bus, with a seed
+        // of their own so their quantization noise is decorrelated. They are
+        // made here since the audio thread can't allocate.
+        while self.buses.len() < MAX_CHANNELS / 2 - 1 {
+            let instance = INSTANCES.fetch_add(1, Ordering::Relaxed);
+            let mut bus = Effect::chain(Arc::clone(&self.params), false);
+            bus.seed = SEED.wrapping_add(instance.wrapping_mul(2));
+            bus.reseed();
+            bus.set_sample_rate(self.sr as f32);
+            self.buses.push(bus);
+        }
+    }
+
+    fn suspend(&mut self) {
+        // state left from before would otherwise come out as a thump when
+        // processing resumes
+        self.reset_state();
+        for bus in self.buses.iter_mut() {
+            bus.reset_state();
+        }
+    }
+
+    fn get_info(&self) -> Info {
+        Info {
+            name: "HYSTERESIS".to_string(),
+            vendor: "Rust DSP".to_string(),
+            unique_id: 0x2d4e04e1,  // adler-32 of name + version (HYSTERESIS v0.3.x)
+            version: 31,
+            inputs: MAX_CHANNELS as i32,
+            outputs: MAX_CHANNELS as i32,
+            // This `parameters` bit is important; without it, none of our
+            // parameters will be shown!
+            parameters: NUM_PARAMS,
+            category: Category::Effect,
+            initial_delay: self.latency() as i32,
+            presets: presets::FACTORY.len() as i32,
+            preset_chunks: true,
+            ..Default::default()
+        }
+    }
+
+    fn set_sample_rate(&mut self, rate: f32){
+        self.sr = rate as f64;
+        self.rate = 1.0/self.sr;
+        self.fb_l.set_cutoff(FB_CUTOFF, self.rate);
+        self.fb_r.set_cutoff(FB_CUTOFF, self.rate);
+        self.trans_fast.set_times(TRANS_FAST, TRANS_RELEASE, self.rate);
+        self.trans_slow.set_times(TRANS_SLOW, TRANS_RELEASE, self.rate);
+        self.level_env.set_times(LEVEL_ATTACK, LEVEL_RELEASE, self.rate);
+        self.noise_gate.set_rate(self.rate);
+        self.idle.set_rate(self.rate);
+        self.dc_l.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.dc_r.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.even_l.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.even_r.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.bias.set_time(BIAS_TIME, self.rate);
+        self.bias_l.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.bias_r.set_cutoff(COUPLING_CUTOFF, self.rate);
+        self.in_l.set_cutoff(analog_cutoff(self.sr), self.rate);
+        self.in_r.set_cutoff(analog_cutoff(self.sr), self.rate);
+        self.trap_l = bias_trap(self.sr);
+        self.trap_r = bias_trap(self.sr);
+        self.loud_in.set_rate(self.rate);
+        self.loud_out.set_rate(self.rate);
+        self.ms_dry.set_rate(self.rate);
+        self.ms_wet.set_rate(self.rate);
+        self.splice.set_rate(self.rate);
+        self.vinyl.set_rate(self.rate);
+        self.transport.set_rate(self.rate);
+        self.hiss.set_rate(self.rate);
+        self.dropout_l.set_rate(self.rate);
+        self.dropout_r.set_rate(self.rate);
+        self.gap_l = GapLoss::new(gap_len(self.sr));
+        self.gap_r = GapLoss::new(gap_len(self.sr));
+        self.spread = Allpass::new(spread_len(self.sr), SPREAD_G);
+        self.sat_in.set_times(SAT_ATTACK, SAT_RELEASE, self.rate);
+        self.sat_out.set_times(SAT_ATTACK, SAT_RELEASE, self.rate);
+        self.dry_wet.set_time(SMOOTH_TIME, self.rate);
+        self.pre_post.set_time(GLIDE_TIME, self.rate);
+        self.threshold.set_time(GLIDE_TIME, self.rate);
+        self.sq.set_time(SMOOTH_TIME, self.rate);
+        self.coerc.set_time(SMOOTH_TIME, self.rate);
+        self.head_cut.set_time(SMOOTH_TIME, self.rate);
+        self.coupling_by.set_rate(self.rate);
+        self.analog_by.set_rate(self.rate);
+        self.trap_by.set_rate(self.rate);
+        self.transient_by.set_rate(self.rate);
+        self.match_by.set_rate(self.rate);
+        self.wet_match_by.set_rate(self.rate);
+        self.safety_by.set_rate(self.rate);
+        self.hiss_lows_by.set_rate(self.rate);
+        self.level_by.set_rate(self.rate);
+        self.mat_by.set_rate(self.rate);
+        self.set_meter_mode(self.meter_mode);
+        self.tp_hold.set_times(0.0, METER_RELEASE, self.rate);
+        self.la = lookahead_lines(self.sr);
+        self.la_len = lookahead_len(self.sr);
+        self.src_l = FixedRate::new(self.sr);
+        self.src_r = FixedRate::new(self.sr);
+        self.os_dry = dry_lines(self.sr);
+        self.recover_l.set_rate(self.rate);
+        self.recover_r.set_rate(self.rate);
+        self.set_drive_eq(self.eq_tilt);
+        self.set_harm_hpf(self.harm_cut);
+        self.set_thickness(self.thick_gain);
+        self.set_band(self.band_lo, self.band_hi);
+        self.set_head_eq(self.head_speed);
+        self.band_rest = stage_lines(self.sr);
+
+        // the host only changes the rate while not processing, state left over
+        // from the old rate would otherwise come out as a glitch at the start
+        // of the next buffer
+        self.reset_state();
+        for bus in self.buses.iter_mut() {
+            bus.set_sample_rate(rate);
+        }
+    }
+
+    fn get_input_info(&self, input: i32) -> ChannelInfo {
+        channel_info(input)
+    }
+
+    fn get_output_info(&self, output: i32) -> ChannelInfo {
+        channel_info(output)
+    }
+
+    // Here is where the bulk of our audio processing code goes.
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let _ftz = FlushToZero::new();
+        self.begin_block(buffer.samples());
+        let samples = buffer.samples();
+
+        let (inputs, mut outputs) = buffer.split();
+        // the host passes what it connects, from a single channel up to
+        // the declared MAX_CHANNELS
+        let channels = inputs.len().min(outputs.len()).min(MAX_CHANNELS);
+
+        // the channels go through in pairs, the first pair through this
+        // chain and the others through the bus chains. A mono bus, or the odd
+        // channel out, runs through one model of its chain.
+        let mut done = 0;
+        for pair in 0..channels.div_ceil(2) {
+            let first = 2 * pair;
+            let chain = match pair {
+                0 => &mut *self,
+                _ => match self.buses.get_mut(pair - 1) {
+                    Some(bus) => bus,
+                    None => break,
+                },
+            };
+            let (out, rest) = outputs.split_at_mut((channels - first).min(2));
+            outputs = rest;
+            done = first + out.len();
+            let (mut l, mut r) = out.split_at_mut(1);
+            chain.mono = r.is_empty();
+            let left_in = inputs.get(first);
+            let right_in = inputs.get((first + 1).min(channels - 1));
+
+            // a block of silence is skipped once the chain has gone quiet.
+            // The host passes every channel even when fewer are connected, so
+            // this also keeps the unconnected ones of a bus cheap.
+            let peak = |x: &[f32]| x.iter().fold(0.0, |p: f64, s| p.max(s.abs() as f64));
+            let peak_in = peak(left_in).max(peak(right_in));
+            if chain.idle.skip(peak_in) {
+                l[0].fill(0.0);
+                if !r.is_empty() {
+                    r[0].fill(0.0);
+                }
+                continue;
+            }
+
+            let mut peak_out: f64 = 0.0;
+            if r.is_empty() {
+                for (mono_in, mono_out) in left_in.iter().zip(l[0].iter_mut()) {
+                    let y = chain.process_sample(*mono_in as f64, *mono_in as f64).0;
+                    *mono_out = y as f32;
+                    peak_out = peak_out.max(y.abs());
+                }
+            } else {
+                // Iterate over inputs as (&f32, &f32)
+                let stereo_in = left_in.iter().zip(right_in.iter());
+
+                // Iterate over outputs as (&mut f32, &mut f32)
+                let stereo_out = l[0].iter_mut().zip(r[0].iter_mut());
+
+                // process
+                for ((left_in, right_in), (left_out, right_out)) in stereo_in.zip(stereo_out) {
+                    let (yl, yr) = chain.process_sample(*left_in as f64, *right_in as f64);
+                    *left_out  = yl as f32;
+                    *right_out = yr as f32;
+                    peak_out = peak_out.max(yl.abs().max(yr.abs()));
+                }
+            }
+            chain.idle.update(peak_in, peak_out, samples);
+        }
+
+        // the bus chains are made when the host first resumes the plugin,
+        // until then the channels past the first pair pass through
+        for ch in done..channels {
+            outputs.get_mut(ch - done).copy_from_slice(inputs.get(ch));
+        }
+
+        self.publish_meters();
+    }
+
+    // Return the parameter object. This method can be omitted if the
+    // plugin has no parameters
+    fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
+        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+    }
+}
+
+impl PluginParameters for EffectParameters {
+    // the `get_parameter` function reads the value of a parameter.
+    fn get_parameter(&self, index: i32) -> f32 {
+        match index {
+            0 => self.pre_post.get(),
+            1 => self.dbg_sq.get(),
+            2 => self.dbg_coerc.get(),
+            3 => self.dry_wet.get(),
+            4 => self.feedback.get(),
+            5 => self.threshold.get(),
+            6 => self.transient.get(),
+            7 => self.ceiling.get(),
+            8 => self.auto_level.get(),
+            9 => self.analog_in.get(),
+            10 => self.lookahead.get(),
+            11 => self.bias_trap.get(),
+            12 => self.loudness_match.get(),
+            13 => self.splice_rate.get(),
+            14 => self.splice_level.get(),
+            15 => self.spread.get(),
+            16 => self.mix_law.get(),
+            17 => self.gap_loss.get(),
+            18 => self.auto_drive.get(),
+            19 => self.drive_target.get(),
+            20 => self.loop_lock.get(),
+            21 => self.clean_blend.get(),
+            22 => self.dyn_coerc.get(),
+            23 => self.dc_coupled.get(),
+            24 => self.quant.get(),
+            25 => self.head_cut.get(),
+            26 => self.material.get(),
+            27 => self.oversampling.get(),
+            28 => self.feedback_tone.get(),
+            29 => self.even.get(),
+            30 => self.stereo_mode.get(),
+            31 => self.width_depth.get(),
+            32 => self.load_balance.get(),
+            33 => self.drive_eq.get(),
+            34 => self.medium.get(),
+            35 => self.harm_hpf.get(),
+            36 => self.thickness.get(),
+            37 => self.fixed_rate.get(),
+            38 => self.meter_mode.get(),
+            39 => self.band_low.get(),
+            40 => self.band_high.get(),
+            41 => self.wow_depth.get(),
+            42 => self.wow_rate.get(),
+            43 => self.flutter_depth.get(),
+            44 => self.flutter_rate.get(),
+            45 => self.hiss.get(),
+            46 => self.head_eq.get(),
+            47 => self.tape_speed.get(),
+            48 => self.link.get(),
+            49 => self.bias.get(),
+            50 => self.xover.get(),
+            51 => self.dropout_rate.get(),
+            52 => self.dropout_depth.get(),
+            53 => self.wet_match.get(),
+            54 => self.safety.get(),
+            55 => self.safety_ceiling.get(),
+            56 => self.rhythmic.get(),
+            57 => self.rhythm_depth.get(),
+            58 => self.hiss_mono_lows.get(),
+            59 => self.instability.get(),
+            60 => self.noise_gate.get(),
+            61 => self.stereo_flutter.get(),
+            _ => 0.0,
+        }
+    }
+
+    // the `set_parameter` function sets the value of a parameter.
+    fn set_parameter(&self, index: i32, val: f32) {
+        if self.is_locked(index) {
+            return;
+        }
+        self.store(index, val);
+    }
+
+    // This is what will display underneath our control.  We can
+    // format it into a string that makes the most since.
+    fn get_parameter_text(&self, index: i32) -> String {
+        match index {
+            // 0 => format!("{:.2} dB", (self.pre_gain.get()*2.0).powf(2.0).log10()*20.0 ),
+            0 => format!("pre: {:.2} dB", 
+                self.pre_post.get() *  24.0 - 12.0),
+            1 => format!("{:.2}", squareness(self.dbg_sq.get() as f64)),
+            2 => format!("{:.2}", self.dbg_coerc.get()),
+            3 => format!("{:.1}% wet", self.dry_wet.get()*100.0),
+            4 => format!("{:.1}%", self.feedback.get() as f64 * FB_MAX * 100.0),
+            5 => format!("{:.1} dBFS", self.threshold.get() * 36.0 - 36.0),
+            6 => (if self.transient.get() > 0.5 { "on" } else { "off" }).to_string(),
+            7 => format!("{:.1} dBFS", self.ceiling.get() * 24.0 - 24.0),
+            8 => (if self.auto_level.get() > 0.5 { "on" } else { "off" }).to_string(),
+            9 => (if self.analog_in.get() > 0.5 { "on" } else { "off" }).to_string(),
+            10 => (if self.lookahead.get() > 0.5 { "on" } else { "off" }).to_string(),
+            11 => (if self.bias_trap.get() > 0.5 { "on" } else { "off" }).to_string(),
+            12 => (if self.loudness_match.get() > 0.5 { "on" } else { "off" }).to_string(),
+            13 => format!("{:.2} /s", self.splice_rate.get() as f64 * SPLICE_MAX),
+            14 => format!("{:.1} dB", self.splice_level.get() * 48.0 - 60.0),
+            15 => format!("{:.1}%", self.spread.get() * 100.0),
+            16 => self.mix_law().name().to_string(),
+            17 => {
+                let gap = self.gap_loss.get() as f64 * GAP_MAX;
+                if gap > 0.0 {
+                    format!("{:.1} um, null at {:.0} Hz", gap * 1e6, self.tape_speed().speed() / gap)
+                } else {
+                    "off".to_string()
+                }
+            }
+            18 => (if self.auto_drive.get() > 0.5 { "on" } else { "off" }).to_string(),
+            19 => format!("{:.1}%", self.drive_target.get() as f64 * DRIVE_TARGET_MAX * 100.0),
+            20 => (if self.loop_lock.get() > 0.5 { "on" } else { "off" }).to_string(),
+            21 => {
+                let clean = self.clean_blend.get();
+                if clean > 0.0 { format!("{:.1} dB", 20.0 * clean.log10()) } else { "off".to_string() }
+            }
+            22 => format!("{:.1}%", self.dyn_coerc.get() * 100.0),
+            23 => self.coupling().name().to_string(),
+            24 => format!("{:.1}%", self.quant.get() * 100.0),
+            25 => {
+                let head = self.head_cut.get() as f64;
+                if head < 1.0 { format!("{:.0} Hz", head_cutoff(head)) } else { "off".to_string() }
+            }
+            26 => self.material().name().to_string(),
+            27 => self.oversampling().name().to_string(),
+            28 => format!("{:.0} Hz", feedback_cutoff(self.feedback_tone.get() as f64)),
+            29 => format!("{:.1}%", self.even.get() * 100.0),
+            30 => self.stereo_mode().name().to_string(),
+            31 => format!("{:.1}%", self.width_depth.get() * 100.0),
+            32 => (if self.load_balance.get() > 0.5 { "on" } else { "off" }).to_string(),
+            33 => format!("{:+.1} dB", self.drive_eq.get() * 2.0 * DRIVE_EQ_MAX as f32 - DRIVE_EQ_MAX as f32),
+            34 => self.medium().name().to_string(),
+            35 => {
+                let cut = harm_hpf(self.harm_hpf.get() as f64);
+                if cut > 0.0 { format!("{:.0} Hz", cut) } else { "off".to_string() }
+            }
+            36 => format!("{:.1} dB", self.thickness.get() as f64 * THICK_MAX),
+            37 => (if self.fixed_rate.get() > 0.5 { "on" } else { "off" }).to_string(),
+            38 => self.meter_mode().name().to_string(),
+            39 => band_text(band_edge(self.band_low.get() as f64, 0.0)),
+            40 => band_text(band_edge(self.band_high.get() as f64, 1.0)),
+            41 => format!("{:.1}%", self.wow_depth.get() * 100.0),
+            42 => format!("{:.2} Hz", wow_rate(self.wow_rate.get() as f64)),
+            43 => format!("{:.1}%", self.flutter_depth.get() * 100.0),
+            44 => format!("{:.1} Hz", flutter_rate(self.flutter_rate.get() as f64)),
+            45 => {
+                let knob = self.hiss.get() as f64;
+                if knob > 0.0 { format!("{:.1} dB", HISS_MIN + (HISS_MAX - HISS_MIN) * knob) } else { "-inf dB".to_string() }
+            }
+            46 => (if self.head_eq.get() > 0.5 { "on" } else { "off" }).to_string(),
+            47 => self.tape_speed().name().to_string(),
+            48 => format!("{:.1}%", self.link.get() * 100.0),
+            49 => format!("{:.1}%", self.bias.get() * 100.0),
+            50 => format!("{:.1}%", self.xover.get() * 100.0),
+            51 => format!("{:.2} /s", self.dropout_rate.get() as f64 * DROPOUT_RATE_MAX),
+            52 => format!("{:.1}%", self.dropout_depth.get() * 100.0),
+            53 => (if self.wet_match.get() > 0.5 { "on" } else { "off" }).to_string(),
+            54 => (if self.safety.get() > 0.5 { "on" } else { "off" }).to_string(),
+            55 => format!("{:.1} dBFS", self.safety_ceiling.get() * 24.0 - 24.0),
+            56 => (if self.rhythmic.get() > 0.5 { "on" } else { "off" }).to_string(),
+            57 => format!("{:.1}%", self.rhythm_depth.get() * 100.0),
+            58 => (if self.hiss_mono_lows.get() > 0.5 { "on" } else { "off" }).to_string(),
+            59 => format!("{:.1}%", self.instability.get() * 100.0),
+            60 => {
+                let knob = self.noise_gate.get() as f64;
+                if knob > 0.0 { format!("{:.1} dBFS", NOISE_GATE_MIN + (NOISE_GATE_MAX - NOISE_GATE_MIN) * knob) } else { "off".to_string() }
+            }
+            61 => format!("{:.1}%", self.stereo_flutter.get() * 100.0),
+            _ => "".to_string(),
+        }
+    }
+
+    // This shows the control's name.
+    fn get_parameter_name(&self, index: i32) -> String {
+        match index {
+            0 => "pre/post gain",
+            1 => "squareness",
+            2 => "coercitivity",
+            3 => "dry/wet",
+            4 => "feedback",
+            5 => "saturation threshold",
+            6 => "transient preserve",
+            7 => "ceiling",
+            8 => "auto level",
+            9 => "analog input",
+            10 => "lookahead",
+            11 => "bias trap",
+            12 => "loudness match",
+            13 => "splice rate",
+            14 => "splice level",
+            15 => "harmonic spread",
+            16 => "mix law",
+            17 => "gap loss",
+            18 => "auto drive",
+            19 => "target saturation",
+            20 => "loop-lock noise",
+            21 => "clean blend",
+            22 => "dynamic coercitivity",
+            23 => "input coupling",
+            24 => "quantization",
+            25 => "head cutoff",
+            26 => "material",
+            27 => "oversampling",
+            28 => "feedback tone",
+            29 => "even harmonics",
+            30 => "stereo mode",
+            31 => "drive-linked width",
+            32 => "load balancing",
+            33 => "drive EQ",
+            34 => "medium",
+            35 => "harmonic HPF",
+            36 => "thickness",
+            37 => "fixed rate",
+            38 => "meter mode",
+            39 => "band low",
+            40 => "band high",
+            41 => "wow depth",
+            42 => "wow rate",
+            43 => "flutter depth",
+            44 => "flutter rate",
+            45 => "hiss level",
+            46 => "head EQ",
+            47 => "tape speed",
+            48 => "channel link",
+            49 => "bias",
+            50 => "under-bias",
+            51 => "dropout rate",
+            52 => "dropout depth",
+            53 => "wet gain match",
+            54 => "safety clip",
+            55 => "safety ceiling",
+            56 => "rhythmic noise",
+            57 => "rhythm depth",
+            58 => "hiss mono lows",
+            59 => "instability",
+            60 => "noise gate",
+            61 => "stereo flutter",
+            _ => "",
+        }
+        .to_string()
+    }
+
+    // factory presets, selecting one sets every parameter, the ones it
+    // doesn't name to their defaults. Locked parameters change too.
+    fn change_preset(&self, preset: i32) {
+        if let Some(factory) = presets::FACTORY.get(preset as usize) {
+            self.reset_to_default(false);
+            for &(index, value) in factory.values {
+                self.store(index, value);
+            }
+            self.program.store(preset as usize, Ordering::Relaxed);
+        }
+    }
+
+    fn get_preset_num(&self) -> i32 {
+        self.program.load(Ordering::Relaxed) as i32
+    }
+
+    fn get_preset_name(&self, preset: i32) -> String {
+        presets::FACTORY.get(preset as usize).map_or("", |p| p.name).to_string()
+    }
+
+    // presets are saved as a versioned blob of every parameter, blobs from
+    // versions with fewer parameters leave the newer ones at their values
+    fn get_preset_data(&self) -> Vec<u8> {
+        code::to_bytes(&self.values())
+    }
+
+    fn get_bank_data(&self) -> Vec<u8> {
+        self.get_preset_data()
+    }
+
+    fn load_preset_data(&self, data: &[u8]) {
+        if let Some(values) = code::from_bytes(data) {
+            self.apply_values(&values);
+        }
+    }
+
+    fn load_bank_data(&self, data: &[u8]) {
+        self.load_preset_data(data);
+    }
+}
+
+/// squareness the model runs at for a knob position. The knob follows a
+/// raised cosine, so the ends where the loop shape changes fastest get more
+/// travel, and is kept inside SQ_MIN - SQ_MAX away from the degenerate loops.
+/// + knob:     parameter value, 0 - 1
+fn squareness(knob: f64) -> f64 {
+    let t = 0.5 - 0.5 * (knob.clamp(0.0, 1.0) * std::f64::consts::PI).cos();
+    SQ_MIN + (SQ_MAX - SQ_MIN) * t
+}
+
+/// harmonic high-pass cutoff in Hz for a knob position, exponential in
+/// frequency, 0 at the bottom of the knob which turns it off
+fn harm_hpf(knob: f64) -> f64 {
+    if knob > 0.0 { HARM_HPF_MIN * (HARM_HPF_MAX / HARM_HPF_MIN).powf(knob) } else { 0.0 }
+}
+
+/// saturation band edge in Hz for a knob position, exponential in frequency
+/// + knob:     knob position, 0 - 1
+/// + off:      knob position that turns the edge off, where it returns 0
+fn band_edge(knob: f64, off: f64) -> f64 {
+    if knob == off { 0.0 } else { BAND_MIN * (BAND_MAX / BAND_MIN).powf(knob) }
+}
+
+/// display text of a saturation band edge
+fn band_text(edge: f64) -> String {
+    if edge <= 0.0 {
+        "off".to_string()
+    } else if edge >= 1000.0 {
+        format!("{:.2} kHz", edge / 1000.0)
+    } else {
+        format!("{:.0} Hz", edge)
+    }
+}
+
+/// playback head cutoff in Hz for a knob position, exponential in frequency
+fn head_cutoff(knob: f64) -> f64 {
+    HEAD_MIN * (HEAD_MAX / HEAD_MIN).powf(knob)
+}
+
+/// cutoff of the analog input roll-off for a given sample rate
+fn analog_cutoff(sr: f64) -> f64 {
+    ANALOG_CUTOFF.min(sr * 0.35)
+}
+
+/// length of the lookahead delay in samples for a given sample rate
+fn lookahead_len(sr: f64) -> usize {
+    ((LOOKAHEAD * sr).round() as usize).max(1)
+}
+
+/// longest gap loss window in samples for a given sample rate
+fn gap_len(sr: f64) -> usize {
+    (GAP_MAX / TapeSpeed::Ips7.speed() * sr).ceil() as usize
+}
+
+/// delay of the harmonic spread allpass in samples for a given sample rate
+fn spread_len(sr: f64) -> usize {
+    (SPREAD_DELAY * sr).round() as usize
+}
+
+/// bias trap low-pass for a given sample rate
+fn bias_trap(sr: f64) -> Biquad {
+    let mut f = Biquad::new();
+    f.set_lowpass(BIAS_TRAP.min(sr * 0.45), 0.707, 1.0 / sr);
+    f
+}
+
+/// lookahead delay lines for a given sample rate
+fn lookahead_lines(sr: f64) -> [DelayLine; 4] {
+    let len = lookahead_len(sr);
+    [DelayLine::new(len), DelayLine::new(len), DelayLine::new(len), DelayLine::new(len)]
+}
+
+/// dry delay lines long enough for the latency of the nonlinear stage, with
+/// either oversampling or the fixed rate conversion at a given sample rate
+fn stage_lines(sr: f64) -> [DelayLine; 2] {
+    let len = oversample::latency(oversample::MAX_STAGES).max(FixedRate::new(sr).latency());
+    [DelayLine::new(len), DelayLine::new(len)]
+}
+
+/// dry delay lines, long enough for the nonlinear stage and the center of the
+/// wow and flutter on top of it
+fn dry_lines(sr: f64) -> [DelayLine; 2] {
+    let len = stage_lines(sr)[0].max_delay() + Transport::new(1.0 / sr).latency()
+        + Vinyl::new(1.0 / sr).latency();
+    [DelayLine::new(len), DelayLine::new(len)]
+}
+
+/// hiss level, linear, for a knob position, linear in dB and 0 at the bottom
+/// of the knob which turns it off
+fn hiss_level(knob: f64) -> f64 {
+    if knob > 0.0 { db_to_gain(HISS_MIN + (HISS_MAX - HISS_MIN) * knob) } else { 0.0 }
+}
+
+/// noise gate threshold, linear, for a knob position, linear in dB and None
+/// at the bottom of the knob which turns it off
+fn noise_gate(knob: f64) -> Option<f64> {
+    if knob > 0.0 { Some(db_to_gain(NOISE_GATE_MIN + (NOISE_GATE_MAX - NOISE_GATE_MIN) * knob)) } else { None }
+}
+
+/// wow rate in Hz for a knob position, exponential in frequency
+fn wow_rate(knob: f64) -> f64 {
+    WOW_RATE_MIN * (WOW_RATE_MAX / WOW_RATE_MIN).powf(knob)
+}
+
+/// flutter rate in Hz for a knob position, exponential in frequency
+fn flutter_rate(knob: f64) -> f64 {
+    FLUTTER_RATE_MIN * (FLUTTER_RATE_MAX / FLUTTER_RATE_MIN).powf(knob)
+}
+
+/// feedback low-pass cutoff in Hz for a tone knob position, exponential in
+/// frequency
+fn feedback_cutoff(knob: f64) -> f64 {
+    FB_TONE_MIN * (FB_TONE_MAX / FB_TONE_MIN).powf(knob)
+}
+
+/// input sample made safe for the processing, non-finite values become
+/// silence, huge ones are held at IN_LIMIT and denormals are flushed
+fn sanitize(x: f64) -> f64 {
+    if x.is_finite() { flush(x.clamp(-IN_LIMIT, IN_LIMIT)) } else { 0.0 }
+}
+
+/// one channel of the nonlinear stage, the hysteresis followed by the
+/// material curve, oversampled. If the output goes non-finite the model and
+/// its oversampler start over and the sample is silenced, so one bad sample
+/// can't mute the channel for good, then the output fades back in.
+/// + os:       oversampler of the channel
+/// + hyst:     hysteresis model of the channel
+/// + recover:  fade-in after a reset
+/// + x:        input, already limited to SAT_IN_LIMIT
+/// + stages:   number of 2x oversampling stages
+/// + sat:      material curve
+fn saturate(os: &mut Oversampler, mut src: Option<&mut FixedRate>, hyst: &mut Hysteresis,
+            recover: &mut Recovery, x: f64, stages: usize, sat: impl Fn(f64) -> f64) -> f64 {
+    let f = |x| sat(hyst.step(x).clamp(-SAT_OUT_LIMIT, SAT_OUT_LIMIT));
+    let y = match src.as_mut() {
+        Some(src) => src.process(flush(x), f),
+        None => os.process(flush(x), stages, f),
+    };
+    if y.is_finite() {
+        recover.step(y)
+    } else {
+        *hyst = Hysteresis::new();
+        os.reset();
+        if let Some(src) = src {
+            src.reset();
+        }
+        recover.trigger();
+        0.0
+    }
+}
+
+/// one step of the feedback path, clears the state on non-finite values. The
+/// path has its own gentle saturation, bounded at unity, so whatever the main
+/// stage does the loop gain stays below FB_MAX.
+/// + lp:   feedback low-pass
+/// + x:    current wet output
+fn fb_update(lp: &mut OnePole, x: f64) {
+    if !lp.step(compute::mag_sat_2(x)).is_finite() {
+        lp.reset();
+    }
+}
+
+/// dry/wet crossfade
+/// + dry:          dry signal
+/// + t:            wet amount, 0 - 1
+/// + wet:          wet signal
+/// + equal_power:  use a sin/cos law instead of a linear one
+fn mix(dry: f64, t: f64, wet: f64, equal_power: bool) -> f64 {
+    if equal_power {
+        let a = t * std::f64::consts::FRAC_PI_2;
+        dry * a.cos() + wet * a.sin()
+    } else {
+        x_fade(dry, t, wet)
+    }
+}
+
+/// name and speaker of a bus channel, the bus being a 7.1 layout in the
+/// stereo pairs it is processed in
+fn channel_info(index: i32) -> ChannelInfo {
+    const NAMES: [(&str, &str); MAX_CHANNELS] = [
+        ("Left", "L"), ("Right", "R"),
+        ("Center", "C"), ("LFE", "LFE"),
+        ("Left Surround", "Ls"), ("Right Surround", "Rs"),
+        ("Side Left", "Sl"), ("Side Right", "Sr"),
+    ];
+    let i = (index.max(0) as usize).min(MAX_CHANNELS - 1);
+    let pair = match i / 2 {
+        0 => StereoConfig::L_R,
+        1 => StereoConfig::C_Lfe,
+        2 => StereoConfig::Ls_Rs,
+        _ => StereoConfig::Sl_Sr,
+    };
+    let side = if i % 2 == 1 { StereoChannel::Right } else { StereoChannel::Left };
+    let (name, short) = NAMES[i];
+    ChannelInfo::new(
+        name.to_string(),
+        Some(short.to_string()),
+        true,
+        Some(SpeakerArrangementType::Stereo(pair, side)),
+    )
+}
+
+// This part is important!  Without it, our plugin won't work.
+plugin_main!(Effect);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::{Deref, DerefMut};
+    use std::sync::{Mutex, MutexGuard};
+
+    const SR: f64 = 44100.0;
+
+    // LIVE counts the instances of the whole process while the tests run in
+    // parallel. Theirs are made and dropped under this lock, and a test
+    // counting them holds it.
+    static LIVE_LOCK: Mutex<()> = Mutex::new(());
+
+    fn live_lock() -> MutexGuard<'static, ()> {
+        // a failed test poisons it, the count is still right
+        LIVE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// an instance with every parameter at its default, made and dropped
+    /// under LIVE_LOCK
+    struct Instance(Option<Effect>);
+
+    fn instance() -> Instance {
+        let _live = live_lock();
+        Instance(Some(Effect::default()))
+    }
+
+    impl Deref for Instance {
+        type Target = Effect;
+        fn deref(&self) -> &Effect {
+            self.0.as_ref().unwrap()
+        }
+    }
+
+    impl DerefMut for Instance {
+        fn deref_mut(&mut self) -> &mut Effect {
+            self.0.as_mut().unwrap()
+        }
+    }
+
+    impl Drop for Instance {
+        fn drop(&mut self) {
+            let _live = live_lock();
+            self.0.take();
+        }
+    }
+
+    /// an instance at SR with every parameter at its default
+    fn effect() -> Instance {
+        let mut e = instance();
+        e.set_sample_rate(SR as f32);
+        e
+    }
+
+    /// run a block of stereo samples through the chain
+    fn run(e: &mut Effect, input: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        e.begin_block(input.len());
+        input.iter().map(|&(l, r)| e.process_sample(l, r)).collect()
+    }
+
+    /// the same sine on both channels
+    fn sine(freq: f64, amp: f64, len: usize) -> Vec<(f64, f64)> {
+        (0..len)
+            .map(|n| {
+                let x = amp * (2.0 * std::f64::consts::PI * freq * n as f64 / SR).sin();
+                (x, x)
+            })
+            .collect()
+    }
+
+    /// highest magnitude on either channel
+    fn peak(x: &[(f64, f64)]) -> f64 {
+        x.iter().fold(0.0, |p, s| p.max(s.0.abs()).max(s.1.abs()))
+    }
+
+    /// amplitude of one frequency in the left channel, the slice should span
+    /// a whole number of its periods
+    fn tone(x: &[(f64, f64)], freq: f64) -> f64 {
+        tone_at(x, freq, SR)
+    }
+
+    /// tone, for a slice at another sample rate
+    fn tone_at(x: &[(f64, f64)], freq: f64, sr: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * freq / sr;
+        let (re, im) = x.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, s)| {
+            (re + s.0 * (w * n as f64).cos(), im + s.0 * (w * n as f64).sin())
+        });
+        2.0 * (re * re + im * im).sqrt() / x.len() as f64
+    }
+
+    #[test]
+    fn feedback_colors_the_output_and_stays_bounded() {
+        let x = sine(100.0, 0.5, 4410);
+        let plain = run(&mut effect(), &x);
+        let mut e = effect();
+        e.params.set_parameter(4, 1.0);
+        let fed = run(&mut e, &x);
+        assert!(plain.iter().zip(fed.iter()).any(|(a, b)| (a.0 - b.0).abs() > 1e-6));
+
+        // every amount and tone of feedback on a sustained hot input
+        let hot = sine(100.0, 16.0, 11025);
+        for fb in 0..=10 {
+            for tone in 0..=10 {
+                let mut e = effect();
+                e.params.set_parameter(4, fb as f32 / 10.0);
+                e.params.set_parameter(28, tone as f32 / 10.0);
+                let y = run(&mut e, &hot);
+                assert!(y.iter().all(|y| y.0.is_finite() && y.1.is_finite()), "{} {}", fb, tone);
+                assert!(peak(&y) < 4.0, "{} {} {}", fb, tone, peak(&y));
+            }
+        }
+    }
+
+    #[test]
+    fn lower_threshold_saturates_harder() {
+        let x = sine(200.0, 0.05, 22050);
+        let mut at_0 = effect();
+        run(&mut at_0, &x);
+        let mut low = effect();
+        low.params.set_parameter(5, 0.0);
+        run(&mut low, &x);
+        assert!(low.saturation() > at_0.saturation() + 0.1);
+        assert_eq!(low.params.get_parameter_text(5), "-36.0 dBFS");
+    }
+
+    #[test]
+    fn transient_preserve_keeps_clicks_dry() {
+        let drive = |transient: f32| {
+            let e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(6, transient);
+            e
+        };
+        let mut click = vec![(0.0, 0.0); 4410];
+        click.push((0.5, 0.5));
+        let (mut off, mut on) = (drive(0.0), drive(1.0));
+        let y_off = run(&mut off, &click)[4410].0;
+        let y_on = run(&mut on, &click)[4410].0;
+        let click_dry = (y_on - y_off) / (0.5 - y_off);
+        assert!(click_dry > 0.5);
+
+        // a sustained tone stays mostly wet
+        let tone = sine(200.0, 0.5, 22050);
+        let (mut off, mut on) = (drive(0.0), drive(1.0));
+        let y_off = run(&mut off, &tone);
+        let y_on = run(&mut on, &tone);
+        let (mut moved, mut apart) = (0.0, 0.0);
+        for n in 20000..22050 {
+            moved += (y_on[n].0 - y_off[n].0).abs();
+            apart += (tone[n].0 - y_off[n].0).abs();
+        }
+        assert!(moved / apart < 0.5 * click_dry);
+    }
+
+    #[test]
+    fn auto_level_rides_peaks_up_to_the_ceiling() {
+        let mut e = effect();
+        e.params.set_parameter(7, 0.9);
+        e.params.set_parameter(8, 1.0);
+        let ceiling = db_to_gain(0.9 * 24.0 - 24.0);
+        for &amp in &[0.05, 0.5, 0.2] {
+            let y = run(&mut e, &sine(300.0, amp, 22050));
+            assert!(peak(&y) <= ceiling + 1e-9);
+            assert!(peak(&y[13230..]) > 0.8 * ceiling);
+        }
+    }
+
+    #[test]
+    fn analog_input_rolls_off_the_highs() {
+        let x = sine(15000.0, 0.01, 8820);
+        let mut off = effect();
+        let mut on = effect();
+        on.params.set_parameter(9, 1.0);
+        let ratio = tone(&run(&mut on, &x)[4410..], 15000.0) / tone(&run(&mut off, &x)[4410..], 15000.0);
+
+        // response of the one-pole at 15 kHz
+        let a = 1.0 - (-2.0 * std::f64::consts::PI * analog_cutoff(SR) / SR).exp();
+        let w = 2.0 * std::f64::consts::PI * 15000.0 / SR;
+        let expected = a / ((1.0 - (1.0 - a) * w.cos()).powi(2) + ((1.0 - a) * w.sin()).powi(2)).sqrt();
+        assert!(expected < 0.9);
+        assert!((ratio / expected - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn reset_to_default_restores_every_parameter() {
+        let defaults = EffectParameters::default();
+        let p = EffectParameters::default();
+        for i in 0..NUM_PARAMS {
+            p.set_parameter(i, (i * 37 % 100) as f32 / 100.0);
+        }
+        p.reset_to_default(false);
+        for i in 0..NUM_PARAMS {
+            assert_eq!(p.get_parameter(i), defaults.get_parameter(i), "parameter {}", i);
+        }
+        assert!(!p.reset_pending.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn reset_to_default_can_clear_the_state() {
+        let mut e = effect();
+        e.params.set_parameter(4, 1.0);
+        run(&mut e, &sine(100.0, 0.8, 4410));
+        e.params.reset_to_default(true);
+        let y = run(&mut e, &[(0.0, 0.0); 64]);
+        assert!(y.iter().all(|s| *s == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn lookahead_delays_the_output_by_its_latency() {
+        let mut e = effect();
+        e.params.set_parameter(3, 0.0);
+        e.params.set_parameter(10, 1.0);
+        let la = lookahead_len(SR);
+        assert_eq!(e.latency(), la);
+        let mut x = vec![(0.0, 0.0); 2 * la];
+        x[0] = (1.0, 1.0);
+        let y = run(&mut e, &x);
+        assert_eq!(y[la], (1.0, 1.0));
+        assert!(y.iter().enumerate().all(|(n, s)| n == la || *s == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn lookahead_levels_a_burst_from_its_leading_edge() {
+        // samples the start of a burst takes to settle, through auto level
+        let settle = |lookahead: f32| {
+            let mut e = effect();
+            e.params.set_parameter(3, 0.0);
+            e.params.set_parameter(8, 1.0);
+            e.params.set_parameter(10, lookahead);
+            let mut x = vec![(0.0, 0.0); 4410];
+            x.extend(vec![(0.1, 0.1); 4410]);
+            let y = run(&mut e, &x);
+            let start = 4410 + e.latency();
+            let settled = y[start + 4000].0;
+            (0..1000).filter(|n| (y[start + n].0 - settled).abs() > 0.01).count()
+        };
+        assert!(settle(1.0) < settle(0.0) * 3 / 5);
+    }
+
+    #[test]
+    fn switching_slots_restores_their_settings() {
+        let p = EffectParameters::default();
+        p.set_parameter(1, 0.2);
+        p.set_parameter(3, 0.7);
+        p.switch_slot(Slot::B);
+        assert_eq!(p.active_slot(), Slot::B);
+        // B still holds the defaults
+        assert_eq!(p.get_parameter(1), 0.5);
+        assert_eq!(p.get_parameter(3), 1.0);
+        p.set_parameter(1, 0.9);
+
+        p.switch_slot(Slot::A);
+        assert_eq!(p.get_parameter(1), 0.2);
+        assert_eq!(p.get_parameter(3), 0.7);
+        p.switch_slot(Slot::B);
+        assert_eq!(p.get_parameter(1), 0.9);
+        assert_eq!(p.get_parameter(3), 1.0);
+    }
+
+    #[test]
+    fn bias_trap_cuts_above_its_frequency() {
+        let through = |freq: f64, trap: f32| {
+            let mut e = effect();
+            e.params.set_parameter(11, trap);
+            tone(&run(&mut e, &sine(freq, 0.01, 8820))[4410..], freq)
+        };
+        // butterworth response, warped like the bilinear transform
+        let warp = |f: f64| (std::f64::consts::PI * f / SR).tan();
+        let expected = 1.0 / (1.0 + (warp(21000.0) / warp(BIAS_TRAP)).powi(4)).sqrt();
+        let ratio = through(21000.0, 1.0) / through(21000.0, 0.0);
+        assert!(expected < 0.5);
+        assert!((ratio / expected - 1.0).abs() < 0.05);
+        assert!((through(1000.0, 1.0) / through(1000.0, 0.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn compression_ratio_falls_as_the_curve_saturates() {
+        let p = EffectParameters::default();
+        assert!((p.compression_ratio_at(0.001) - 1.0).abs() < 0.05);
+        let ratios: Vec<f32> = [0.1, 0.5, 1.0, 2.0, 4.0].iter().map(|&l| p.compression_ratio_at(l)).collect();
+        assert!(ratios.windows(2).all(|w| w[1] < w[0]));
+        assert!(ratios[4] < 0.5);
+    }
+
+    #[test]
+    fn loudness_match_follows_the_input_loudness() {
+        let x = sine(1000.0, 0.5, 4 * 44100);
+        let rms = |x: &[(f64, f64)]| (x.iter().map(|s| s.0 * s.0).sum::<f64>() / x.len() as f64).sqrt();
+        let tail = |y: &[(f64, f64)]| 20.0 * (rms(&y[y.len() - 22050..]) / rms(&x[x.len() - 22050..])).log10();
+        let driven = |matched: f32| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(12, matched);
+            run(&mut e, &x)
+        };
+        assert!(tail(&driven(0.0)).abs() > 3.0);
+        assert!(tail(&driven(1.0)).abs() < 0.5);
+    }
+
+    #[test]
+    fn set_parameter_rejects_bad_values() {
+        let p = EffectParameters::default();
+        p.set_parameter(3, 0.3);
+        p.set_parameter(3, f32::NAN);
+        assert_eq!(p.get_parameter(3), 0.3);
+        p.set_parameter(3, f32::INFINITY);
+        assert_eq!(p.get_parameter(3), 0.3);
+        p.set_parameter(3, 2.0);
+        assert_eq!(p.get_parameter(3), 1.0);
+        p.set_parameter(3, -1.0);
+        assert_eq!(p.get_parameter(3), 0.0);
+        for i in 0..NUM_PARAMS {
+            p.set_parameter(i, f32::NAN);
+            let v = p.get_parameter(i);
+            assert!(v.is_finite() && (0.0..=1.0).contains(&v), "parameter {}", i);
+        }
+    }
+
+    #[test]
+    fn non_finite_input_gives_finite_output() {
+        let mut e = effect();
+        let mut x = sine(200.0, 0.5, 1000);
+        x[100] = (f64::NAN, f64::INFINITY);
+        x[200] = (f64::NEG_INFINITY, f64::NAN);
+        assert!(run(&mut e, &x).iter().all(|s| s.0.is_finite() && s.1.is_finite()));
+    }
+
+    #[test]
+    fn transparent_settings_pass_the_input_through() {
+        let mut e = effect();
+        assert!(!e.params.is_transparent());
+        e.params.set_parameter(3, 0.0);
+        assert!(e.params.is_transparent());
+        let x = sine(300.0, 0.9, 4410);
+        assert_eq!(run(&mut e, &x), x);
+
+        for &(index, value) in &[(10, 1.0), (13, 0.5), (21, 0.5), (27, 0.5), (54, 1.0)] {
+            let p = EffectParameters::default();
+            p.set_parameter(3, 0.0);
+            p.set_parameter(index, value);
+            assert!(!p.is_transparent(), "parameter {}", index);
+        }
+    }
+
+    #[test]
+    fn harmonic_spread_widens_only_the_harmonics() {
+        let x = sine(200.0, 0.8, 8820);
+        let spread = |amount: f32| {
+            let mut e = effect();
+            e.params.set_parameter(15, amount);
+            run(&mut e, &x)
+        };
+        let side = |y: &[(f64, f64)]| y.iter().map(|s| (0.5 * (s.0 - s.1)).powi(2)).sum::<f64>();
+        let (none, half, full) = (spread(0.0), spread(0.5), spread(1.0));
+        assert_eq!(side(&none), 0.0);
+        assert!(side(&half) > 0.0 && side(&full) > 2.0 * side(&half));
+        // the mid, and with it the mono fundamental, doesn't move
+        for (a, b) in none.iter().zip(&full) {
+            assert!((0.5 * (a.0 + a.1) - 0.5 * (b.0 + b.1)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn sample_rate_change_starts_clean() {
+        let mut e = effect();
+        e.params.set_parameter(4, 1.0);
+        run(&mut e, &sine(100.0, 0.9, 4410));
+        e.set_sample_rate(96000.0);
+        assert!(run(&mut e, &[(0.0, 0.0); 256]).iter().all(|s| *s == (0.0, 0.0)));
+        // nothing from before the change is left, it plays like a new instance
+        let mut fresh = instance();
+        fresh.set_sample_rate(96000.0);
+        fresh.params.set_parameter(4, 1.0);
+        run(&mut fresh, &[(0.0, 0.0); 256]);
+        let x = sine(100.0, 0.5, 256);
+        assert_eq!(run(&mut e, &x), run(&mut fresh, &x));
+    }
+
+    #[test]
+    fn automated_dry_wet_glides() {
+        // the dry DC is blocked on the wet side, so dry/wet moves the level
+        let mut e = effect();
+        run(&mut e, &[(0.5, 0.5); 8820]);
+        let mut y = Vec::new();
+        for block in 0..140 {
+            e.params.set_parameter(3, (block % 2) as f32);
+            y.extend(run(&mut e, &[(0.5, 0.5); 64]));
+        }
+        let jump = y.windows(2).map(|w| (w[1].0 - w[0].0).abs()).fold(0.0, f64::max);
+        let (lo, hi) = y.iter().fold((f64::MAX, f64::MIN), |(lo, hi), s| (lo.min(s.0), hi.max(s.0)));
+        assert!(hi - lo > 0.2);
+        assert!(jump < 0.01);
+    }
+
+    #[test]
+    fn mix_laws() {
+        assert!((mix(1.0, 0.5, 1.0, false) - 1.0).abs() < 1e-12);
+        assert!((mix(1.0, 0.5, 1.0, true) - 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(mix(0.3, 0.0, 0.7, true), 0.3);
+        assert!((mix(0.3, 1.0, 0.7, true) - 0.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gap_loss_null_follows_the_gap_and_the_speed() {
+        // a gap the tape takes 10 samples to cross at 7.5 ips
+        let knob = (10.0 / SR * TapeSpeed::Ips7.speed() / GAP_MAX) as f32;
+        let through = |speed: TapeSpeed, freq: f64| {
+            let mut e = effect();
+            e.params.set_parameter(17, knob);
+            e.params.set_tape_speed(speed);
+            tone(&run(&mut e, &sine(freq, 0.01, 8820))[4410..], freq) / 0.01
+        };
+        assert!(through(TapeSpeed::Ips7, 4410.0) < 0.01);
+        // twice the speed crosses the gap in half the time, the null moves
+        // up an octave
+        assert!(through(TapeSpeed::Ips15, 4410.0) > 0.5);
+        assert!(through(TapeSpeed::Ips15, 8820.0) < 0.01);
+        let p = EffectParameters::default();
+        assert_eq!(p.get_parameter_text(17), "off");
+        p.set_parameter(17, 1.0);
+        assert_eq!(p.get_parameter_text(17), "50.0 um, null at 7620 Hz");
+        p.set_tape_speed(TapeSpeed::Ips30);
+        assert_eq!(p.get_parameter_text(17), "50.0 um, null at 15240 Hz");
+    }
+
+    #[test]
+    fn gains_glide_linearly_in_db() {
+        let mut e = effect();
+        run(&mut e, &[(0.0, 0.0)]);
+        e.params.set_parameter(0, 1.0);
+        run(&mut e, &[(0.0, 0.0); 10]);
+        let db: Vec<f64> = (0..3).map(|_| e.pre_post.next()).collect();
+        // 0 to +12 dB
+        let step = 12.0 / (GLIDE_TIME * SR);
+        assert!((db[1] - db[0] - step).abs() < 1e-9);
+        assert!((db[2] - db[1] - step).abs() < 1e-9);
+    }
+
+    #[test]
+    fn switching_a_stage_is_click_free() {
+        // the safety clip pulls a dry DC down to its ceiling, 0.25 here
+        let mut e = effect();
+        e.params.set_parameter(3, 0.0);
+        e.params.set_parameter(55, 0.5);
+        let mut y = run(&mut e, &[(0.5, 0.5); 1000]);
+        e.params.set_parameter(54, 1.0);
+        y.extend(run(&mut e, &[(0.5, 0.5); 1000]));
+        e.params.set_parameter(54, 0.0);
+        y.extend(run(&mut e, &[(0.5, 0.5); 1000]));
+        assert!(y[1999].0 < 0.26);
+        // fully off is an exact passthrough again
+        assert_eq!(y[2999].0, 0.5);
+        let jump = y.windows(2).map(|w| (w[1].0 - w[0].0).abs()).fold(0.0, f64::max);
+        assert!(jump < 0.002);
+    }
+
+    #[test]
+    fn everything_at_once_stays_finite() {
+        let mut x = sine(100.0, 1.0, 8820);
+        x[2000] = (1e30, -1e30);
+        x[4000] = (f64::MAX, f64::MIN);
+        for setting in 0..3 {
+            let mut e = effect();
+            for i in 0..NUM_PARAMS {
+                let v = match setting {
+                    0 => 1.0,
+                    1 => 0.9,
+                    _ => (i * 37 % 100) as f32 / 100.0,
+                };
+                e.params.set_parameter(i, v);
+            }
+            let y = run(&mut e, &x);
+            assert!(y.iter().all(|s| s.0.abs() <= OUT_LIMIT && s.1.abs() <= OUT_LIMIT), "setting {}", setting);
+            assert!(e.fb_l.get().is_finite() && e.drive.is_finite());
+        }
+    }
+
+    #[test]
+    fn settings_code_round_trip() {
+        let p = EffectParameters::default();
+        for i in 0..NUM_PARAMS {
+            p.set_parameter(i, (i * 37 % 100) as f32 / 99.0);
+        }
+        let code = p.settings_code();
+        let q = EffectParameters::default();
+        assert!(q.apply_code(&code));
+        for i in 0..NUM_PARAMS {
+            assert_eq!(q.get_parameter(i), p.get_parameter(i), "parameter {}", i);
+        }
+
+        let before = q.settings_code();
+        assert!(!q.apply_code("not a code!"));
+        assert!(!q.apply_code(&code[..code.len() / 2]));
+        assert_eq!(q.settings_code(), before);
+    }
+
+    #[test]
+    fn auto_drive_evens_out_the_saturation() {
+        let settle = |amp: f64, auto: f32| {
+            let mut e = effect();
+            e.params.set_parameter(18, auto);
+            for _ in 0..20 {
+                run(&mut e, &sine(200.0, amp, 44100));
+            }
+            (e.saturation(), e.drive)
+        };
+        let spread = settle(0.6, 0.0).0 - settle(0.1, 0.0).0;
+        // the default target
+        let target = 0.25 * DRIVE_TARGET_MAX;
+        let (quiet, up) = settle(0.1, 1.0);
+        let (hot, down) = settle(0.6, 1.0);
+        assert!((hot - quiet).abs() < 0.1 * spread);
+        assert!((quiet - target).abs() < 0.02 && (hot - target).abs() < 0.02);
+        assert!(up > 0.0 && down < 0.0);
+    }
+
+    #[test]
+    fn loop_lock_repeats_the_artifacts() {
+        let mut e = effect();
+        e.params.set_parameter(13, 1.0);
+        e.params.set_parameter(20, 1.0);
+        // one pass of a 4 block loop, playing from its start
+        let pass = |e: &mut Effect| {
+            let mut y = Vec::new();
+            for block in 0..4 {
+                e.follow_position(true, (block * 4410) as f64, 4410);
+                y.extend(run(e, &[(0.0, 0.0); 4410]));
+            }
+            y
+        };
+        let first = pass(&mut e);
+        assert!(peak(&first) > 0.0);
+        let second = pass(&mut e);
+        assert_eq!(first, second);
+
+        // the wrap put the generator back at its seed
+        e.follow_position(true, 0.0, 4410);
+        assert_eq!(e.rng, Xoshiro256Plus::seed_from_u64(SEED));
+    }
+
+    #[test]
+    fn clean_blend_adds_the_aligned_input() {
+        let x = sine(300.0, 0.5, 4410);
+        let blend = |clean: f32| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_oversampling(OversamplingFactor::X2);
+            e.params.set_parameter(21, clean);
+            (run(&mut e, &x), e.latency())
+        };
+        let (without, _) = blend(0.0);
+        let (with, latency) = blend(1.0);
+        assert_eq!(latency, 16);
+        for n in latency..x.len() {
+            assert!((with[n].0 - without[n].0 - x[n - latency].0).abs() < 1e-12);
+        }
+
+        // on top of a dry mix it doubles the input
+        let mut e = effect();
+        e.params.set_parameter(3, 0.0);
+        e.params.set_oversampling(OversamplingFactor::X2);
+        e.params.set_parameter(21, 1.0);
+        let mut impulse = vec![(0.0, 0.0); 64];
+        impulse[0] = (0.5, 0.5);
+        assert_eq!(run(&mut e, &impulse)[16], (1.0, 1.0));
+    }
+
+    #[test]
+    fn dynamic_coercitivity_follows_the_level() {
+        let coerc = |amp: f64, depth: f32| {
+            let mut e = effect();
+            e.params.set_parameter(22, depth);
+            run(&mut e, &sine(200.0, amp, 8820));
+            e.hyst_l.coerc
+        };
+        assert_eq!(coerc(0.01, 0.0), 0.5);
+        assert_eq!(coerc(0.9, 0.0), 0.5);
+        let (quiet, loud) = (coerc(0.01, 1.0), coerc(0.9, 1.0));
+        assert!(quiet < 0.51);
+        assert!(loud > quiet + 0.1 && loud <= 1.0);
+    }
+
+    #[test]
+    fn coupling_decides_whether_dc_reaches_the_saturator() {
+        // long enough for the detector to forget the step at the start
+        let mut ac = effect();
+        run(&mut ac, &[(0.5, 0.5); 88200]);
+        assert!(ac.sat_in.get() < 1e-3);
+        let mut dc = effect();
+        dc.params.set_coupling(Coupling::DC);
+        run(&mut dc, &[(0.5, 0.5); 22050]);
+        assert!((dc.sat_in.get() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn squareness_stays_stable_across_the_knob() {
+        assert_eq!(squareness(0.0), SQ_MIN);
+        assert_eq!(squareness(1.0), SQ_MAX);
+        let mut last = 0.0;
+        for k in 0..=10 {
+            let knob = k as f32 / 10.0;
+            assert!(squareness(knob as f64) > last);
+            last = squareness(knob as f64);
+
+            let mut e = effect();
+            e.params.set_parameter(0, 0.75);
+            e.params.set_parameter(1, knob);
+            let y = run(&mut e, &sine(200.0, 0.5, 8820));
+            // an oscillating model would flip the output every sample
+            let jump = y.windows(2).map(|w| (w[1].0 - w[0].0).abs()).fold(0.0, f64::max);
+            assert!(peak(&y) < 1.0 && jump < 0.1, "squareness {}", knob);
+        }
+    }
+
+    #[test]
+    fn loop_area_grows_with_coercitivity() {
+        let p = EffectParameters::default();
+        let area = |coerc: f32| {
+            p.set_parameter(2, coerc);
+            p.loop_area()
+        };
+        let areas: Vec<f32> = [0.2, 0.5, 0.8].iter().map(|&c| area(c)).collect();
+        assert!(areas.windows(2).all(|w| w[1] > w[0]));
+        assert!(area(0.0) < 0.1 * areas[1]);
+
+        let curve = p.loop_curve();
+        assert_eq!(curve.len(), 4 * LOOP_STEPS + 1);
+        assert_eq!(curve[0].0, 0.0);
+        assert_eq!(curve[LOOP_STEPS].0, LOOP_PEAK as f32);
+    }
+
+    #[test]
+    fn automated_head_cut_stays_bounded() {
+        let mut e = effect();
+        e.params.set_parameter(23, 1.0);
+        let x: Vec<(f64, f64)> = sine(15000.0, 0.3, 441).iter().map(|s| (s.0 + 0.3, s.1 + 0.3)).collect();
+        let mut jump: f64 = 0.0;
+        let mut last = 0.0;
+        for i in 0..200 {
+            e.params.set_parameter(25, if i % 2 == 0 { 0.0 } else { 0.999 });
+            let y = run(&mut e, &x);
+            assert!(y.iter().all(|s| s.0.is_finite()) && peak(&y) < 1.0);
+            jump = jump.max((y[0].0 - last).abs());
+            last = y[440].0;
+        }
+        assert!(jump < 0.2);
+    }
+
+    #[test]
+    fn typed_setters_drive_the_processing() {
+        let x = sine(200.0, 0.8, 4410);
+        let heard = |set: &dyn Fn(&EffectParameters)| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            set(&e.params);
+            run(&mut e, &x)
+        };
+
+        // every material has its own curve
+        let curves: Vec<_> = TapeMaterial::ALL
+            .iter()
+            .map(|&m| heard(&|p: &EffectParameters| {
+                p.set_material(m);
+                assert_eq!(p.material(), m);
+            }))
+            .collect();
+        for (i, a) in curves.iter().enumerate() {
+            for b in curves[i + 1..].iter() {
+                assert!(a.iter().zip(b.iter()).any(|(a, b)| (a.0 - b.0).abs() > 1e-3));
+            }
+        }
+
+        // a left-only input stays on the left unless the channels are linked.
+        // In mid/side its mid and side are equal and stay equal through the
+        // models, so it stays on the left there too.
+        let left: Vec<(f64, f64)> = x.iter().map(|s| (s.0, 0.0)).collect();
+        for &mode in StereoMode::ALL.iter() {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_stereo_mode(mode);
+            assert_eq!(e.params.stereo_mode(), mode);
+            let right = run(&mut e, &left).iter().fold(0.0, |p: f64, s| p.max(s.1.abs()));
+            assert_eq!(right > 1e-3, mode == StereoMode::Linked, "{:?}", mode);
+        }
+
+        // the oversampling factor sets the latency
+        for &f in OversamplingFactor::ALL.iter() {
+            let e = effect();
+            e.params.set_oversampling(f);
+            assert_eq!(e.params.oversampling(), f);
+            assert_eq!(e.latency(), oversample::latency(f.stages()));
+        }
+
+        let p = EffectParameters::default();
+        p.set_coupling(Coupling::DC);
+        p.set_mix_law(MixLaw::EqualPower);
+        p.set_medium(Medium::Vinyl);
+        p.set_tape_speed(TapeSpeed::Ips30);
+        p.set_meter_mode(MeterMode::Vu);
+        assert_eq!(p.coupling(), Coupling::DC);
+        assert_eq!(p.mix_law(), MixLaw::EqualPower);
+        assert_eq!(p.medium(), Medium::Vinyl);
+        assert_eq!(p.tape_speed(), TapeSpeed::Ips30);
+        assert_eq!(p.meter_mode(), MeterMode::Vu);
+    }
+
+    /// an instance with the saturation out of the way, a low level signal
+    /// goes through the wet path unchanged apart from the delay
+    fn clean(factor: OversamplingFactor, dry_wet: f32) -> Instance {
+        let mut e = effect();
+        e.params.set_parameter(2, 0.0);
+        e.params.set_coupling(Coupling::DC);
+        e.params.set_oversampling(factor);
+        e.params.set_parameter(3, dry_wet);
+        run(&mut e, &[(0.0, 0.0); 64]);
+        e
+    }
+
+    #[test]
+    fn wet_nulls_against_dry_at_every_factor() {
+        let freq = 44100.0 / 49.0;
+        let x = sine(freq, 0.01, 4410);
+        for &f in OversamplingFactor::ALL.iter() {
+            let dry = run(&mut clean(f, 0.0), &x);
+            let wet = run(&mut clean(f, 1.0), &x);
+            let err = dry[2205..].iter().zip(wet[2205..].iter())
+                .fold(0.0, |p: f64, (d, w)| p.max((d.0 - w.0).abs()));
+            assert!(err < 0.02 * 0.01, "{:?} {}", f, err);
+        }
+    }
+
+    #[test]
+    fn impulse_lands_on_the_reported_latency() {
+        for &f in OversamplingFactor::ALL.iter() {
+            let mut e = clean(f, 1.0);
+            let mut x = vec![(0.0, 0.0); 256];
+            x[0] = (0.01, 0.01);
+            let y = run(&mut e, &x);
+            let at = (0..y.len()).max_by(|&a, &b| y[a].0.abs().partial_cmp(&y[b].0.abs()).unwrap()).unwrap();
+            assert_eq!(at, e.latency(), "{:?}", f);
+        }
+    }
+
+    #[test]
+    fn feedback_path_is_bounded_by_its_saturation() {
+        let mut lp = OnePole::new(FB_CUTOFF, 1.0 / SR);
+        for n in 0..44100 {
+            let x = 1e3 * (2.0 * std::f64::consts::PI * 100.0 * n as f64 / SR).sin();
+            fb_update(&mut lp, x);
+            assert!(lp.get().abs() <= 1.0);
+        }
+        fb_update(&mut lp, f64::NAN);
+        assert_eq!(lp.get(), 0.0);
+    }
+
+    #[test]
+    fn even_harmonics_are_even_and_dc_free() {
+        let x = sine(441.0, 0.3, 44100);
+        let harmonics = |even: f32| {
+            let mut e = effect();
+            e.params.set_parameter(29, even);
+            let y = run(&mut e, &x);
+            let y = &y[44100 - 4410..];
+            let dc = y.iter().map(|s| s.0).sum::<f64>() / y.len() as f64;
+            let h: Vec<f64> = (2..6).map(|k| tone(y, 441.0 * k as f64)).collect();
+            (h, dc)
+        };
+        let (off, _) = harmonics(0.0);
+        let (on, dc) = harmonics(1.0);
+        assert!(off[0] < 1e-3 && off[2] < 1e-3);
+        // 2nd and 4th come up, 3rd and 5th barely move
+        let even = (on[0] - off[0]) + (on[2] - off[2]);
+        let odd = (on[1] - off[1]).abs() + (on[3] - off[3]).abs();
+        assert!(even > 0.01 && odd < 0.1 * even, "{:?} {:?}", off, on);
+        assert!(dc.abs() < 1e-3);
+    }
+
+    #[test]
+    fn drive_narrows_the_width() {
+        let x: Vec<(f64, f64)> = (0..22050)
+            .map(|n| {
+                let t = 2.0 * std::f64::consts::PI * n as f64 / SR;
+                (0.3 * (200.0 * t).sin(), 0.3 * (310.0 * t).sin())
+            })
+            .collect();
+        let width = |depth: f32, drive: f32| {
+            let mut e = effect();
+            e.params.set_parameter(31, depth);
+            e.params.set_parameter(0, drive);
+            let y = run(&mut e, &x);
+            let (m, s) = y[11025..].iter().fold((0.0, 0.0), |(m, s), y| {
+                (m + (y.0 + y.1).powi(2), s + (y.0 - y.1).powi(2))
+            });
+            s / m
+        };
+        let still = width(0.0, 0.5) / width(0.0, 1.0);
+        assert!((still - 1.0).abs() < 0.05, "{}", still);
+        assert!(width(1.0, 1.0) < 0.9 * width(1.0, 0.5));
+        assert!((width(1.0, 0.5) / width(0.0, 0.5) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn non_finite_model_output_resets_the_model() {
+        let mut e = effect();
+        run(&mut e, &sine(100.0, 0.9, 4410));
+        let c: &mut Effect = &mut e;
+        let y = saturate(&mut c.os_l, None, &mut c.hyst_l, &mut c.recover_l, f64::NAN, 0, compute::mag_sat_3);
+        assert_eq!(y, 0.0);
+        // the model starts over from a fresh state
+        let mut fresh = Hysteresis::new();
+        for n in 0..64 {
+            let x = 0.5 * (n as f64 * 0.1).sin();
+            assert_eq!(e.hyst_l.step(x).to_bits(), fresh.step(x).to_bits());
+        }
+        // and the channel plays on
+        let y = run(&mut e, &sine(100.0, 0.5, 4410));
+        assert!(y.iter().all(|s| s.0.is_finite()) && peak(&y[2205..]) > 0.1);
+    }
+
+    #[test]
+    fn iterator_matches_the_blocks() {
+        let x: Vec<(f32, f32)> = sine(200.0, 0.8, 4096).iter().map(|s| (s.0 as f32, 0.5 * s.1 as f32)).collect();
+        let mut blocks = effect();
+        let mut y = Vec::new();
+        for chunk in x.chunks(512) {
+            let chunk: Vec<(f64, f64)> = chunk.iter().map(|s| (s.0 as f64, s.1 as f64)).collect();
+            y.extend(run(&mut blocks, &chunk).iter().map(|s| (s.0 as f32, s.1 as f32)));
+        }
+        let mut iter = effect();
+        let z: Vec<(f32, f32)> = iter.process_iter(x.iter().copied(), 512).collect();
+        assert_eq!(y, z);
+
+        // a source without a length still gets whole blocks, and the meters
+        // come at the end of each one and at the end of the source
+        let mut e = effect();
+        let params = Arc::clone(&e.params);
+        let mut source = x.iter().copied();
+        let mut out = e.process_iter(std::iter::from_fn(move || source.next()), 64);
+        out.by_ref().take(63).for_each(drop);
+        assert_eq!(params.sample_peak(), 0.0);
+        out.next();
+        assert!(params.sample_peak() > 0.0);
+        let mut e = effect();
+        e.process_iter(x[..10].iter().copied(), 64).for_each(drop);
+        assert!(e.params.sample_peak() > 0.0);
+    }
+
+    #[test]
+    fn many_instances_switch_to_the_economy_filters() {
+        let x = sine(15000.0, 0.8, 2205);
+        let balanced = |on: f32| {
+            let mut e = effect();
+            e.params.set_oversampling(OversamplingFactor::X2);
+            e.params.set_parameter(32, on);
+            (run(&mut e, &x), e.latency())
+        };
+        let crowd: Vec<Instance> = (0..BALANCE_INSTANCES).map(|_| instance()).collect();
+        assert!(LIVE.load(Ordering::Relaxed) >= crowd.len());
+        let (full, full_latency) = balanced(0.0);
+        let (economy, economy_latency) = balanced(1.0);
+        assert_ne!(full, economy);
+        assert_eq!(full_latency, economy_latency);
+        drop(crowd);
+    }
+
+    #[test]
+    fn live_instances_are_counted_exactly() {
+        let _live = live_lock();
+        let before = LIVE.load(Ordering::Relaxed);
+        let crowd: Vec<Effect> = (0..BALANCE_INSTANCES).map(|_| Effect::default()).collect();
+        assert_eq!(LIVE.load(Ordering::Relaxed), before + BALANCE_INSTANCES);
+        // the probe of the null calibration doesn't count
+        crowd[0].null_calibration();
+        assert_eq!(LIVE.load(Ordering::Relaxed), before + BALANCE_INSTANCES);
+        drop(crowd);
+        assert_eq!(LIVE.load(Ordering::Relaxed), before);
+    }
+
+    #[test]
+    fn drive_eq_tilts_what_saturates() {
+        let saturation = |tilt: f32, freq: f64| {
+            let mut e = effect();
+            e.params.set_parameter(33, tilt);
+            run(&mut e, &sine(freq, 0.5, 22050));
+            e.saturation()
+        };
+        let lows_over_highs = |tilt: f32| saturation(tilt, 100.0) - saturation(tilt, 5000.0);
+        let flat = lows_over_highs(0.5);
+        assert!(lows_over_highs(1.0) > flat + 0.05);
+        assert!(lows_over_highs(0.0) < flat - 0.05);
+
+        // quiet signals come out as they went in
+        let x = sine(2000.0, 0.001, 4410);
+        let quiet = |tilt: f32| {
+            let mut e = effect();
+            e.params.set_parameter(33, tilt);
+            run(&mut e, &x)
+        };
+        let flat = quiet(0.5);
+        let tilted = quiet(1.0);
+        let err = flat.iter().zip(tilted.iter()).fold(0.0, |p: f64, (a, b)| p.max((a.0 - b.0).abs()));
+        assert!(err < 0.01 * 0.001);
+    }
+
+    #[test]
+    fn output_fades_back_in_after_a_reset() {
+        let mut e = effect();
+        e.params.set_parameter(3, 1.0);
+        let x = sine(1000.0, 0.5, 4410);
+        run(&mut e, &x);
+        let c: &mut Effect = &mut e;
+        saturate(&mut c.os_l, None, &mut c.hyst_l, &mut c.recover_l, f64::NAN, 0, compute::mag_sat_3);
+        // the output comes back under a ramp over the fade, not as a step
+        let y = run(&mut e, &x);
+        let len = (0.01 * SR) as usize;
+        let settled = peak(&y[2 * len..]);
+        for (n, y) in y.iter().take(len).enumerate() {
+            assert!(y.0.abs() <= 1.1 * settled * (n + 1) as f64 / len as f64, "{}", n);
+        }
+        assert!(peak(&y[len - 44..len]) > 0.75 * settled);
+    }
+
+    #[test]
+    fn vinyl_wow_is_part_of_the_latency() {
+        let mut e = effect();
+        e.params.set_medium(Medium::Vinyl);
+        e.params.set_parameter(14, 0.0);
+        e.params.set_parameter(3, 0.0);
+        assert_eq!(e.latency(), e.vinyl.latency());
+        let mut x = vec![(0.0, 0.0); 256];
+        x[0] = (0.5, 0.5);
+        let y = run(&mut e, &x);
+        let at = (0..y.len()).max_by(|&a, &b| y[a].0.abs().partial_cmp(&y[b].0.abs()).unwrap()).unwrap();
+        assert_eq!(at, e.latency());
+    }
+
+    #[test]
+    fn silencing_settings_raise_the_no_output_status() {
+        let x: Vec<(f32, f32)> = sine(100.0, 0.003, 44100).iter().map(|s| (s.0 as f32, s.1 as f32)).collect();
+        let status = |under_bias: f32| {
+            let mut e = effect();
+            e.params.set_parameter(3, 1.0);
+            e.params.set_parameter(50, under_bias);
+            e.process_iter(x.iter().copied(), 512).for_each(drop);
+            e.params.no_output()
+        };
+        // full under-bias swallows a quiet, fully wet signal in its dead zone
+        assert!(status(1.0));
+        assert!(!status(0.0));
+    }
+
+    #[test]
+    fn harmonic_hpf_keeps_the_sub_bass_dry() {
+        // 30 Hz and 1050 Hz, both whole periods in the last 0.1 s
+        let x: Vec<(f64, f64)> = sine(30.0, 0.4, 44100)
+            .iter()
+            .zip(sine(1050.0, 0.4, 44100).iter())
+            .map(|(a, b)| (a.0 + b.0, a.1 + b.1))
+            .collect();
+        let heard = |hpf: f32| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(35, hpf);
+            let y = run(&mut e, &x);
+            y[44100 - 4410..].to_vec()
+        };
+        let (off, on) = (heard(0.0), heard(1.0));
+        // the processing changes the sub-bass, with the filter it is the input's
+        assert!((tone(&off, 30.0) - 0.4).abs() > 0.01);
+        assert!((tone(&on, 30.0) - 0.4).abs() < 0.005);
+        // harmonics well above the cutoff stay
+        for &h in &[2100.0, 3150.0] {
+            let (a, b) = (tone(&off, h), tone(&on, h));
+            assert!((a - b).abs() < 0.05 * a.max(1e-4), "{} {} {}", h, a, b);
+        }
+    }
+
+    #[test]
+    fn thickness_saturates_the_low_mids() {
+        let heard = |thick: f32, freq: f64, amp: f64| {
+            let mut e = effect();
+            e.params.set_parameter(36, thick);
+            let y = run(&mut e, &sine(freq, amp, 22050));
+            (e.saturation(), y)
+        };
+        let added = |freq: f64| heard(1.0, freq, 0.4).0 - heard(0.0, freq, 0.4).0;
+        assert!(added(250.0) > 0.05);
+        assert!(added(250.0) > 2.0 * added(4000.0).max(0.0));
+
+        // quiet signals come out flat
+        for &freq in &[250.0, 4000.0] {
+            let (_, flat) = heard(0.0, freq, 0.001);
+            let (_, thick) = heard(1.0, freq, 0.001);
+            let err = flat.iter().zip(thick.iter()).fold(0.0, |p: f64, (a, b)| p.max((a.0 - b.0).abs()));
+            assert!(err < 0.01 * 0.001, "{} {}", freq, err);
+        }
+    }
+
+    #[test]
+    fn fixed_rate_sounds_the_same_at_any_host_rate() {
+        let thd = |sr: f64| {
+            let mut e = instance();
+            e.set_sample_rate(sr as f32);
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(37, 1.0);
+            let x: Vec<(f64, f64)> = (0..sr as usize / 2)
+                .map(|n| {
+                    let x = 0.5 * (2.0 * std::f64::consts::PI * 1000.0 * n as f64 / sr).sin();
+                    (x, x)
+                })
+                .collect();
+            let y = run(&mut e, &x);
+            let y = &y[y.len() - sr as usize / 10..];
+            tone_at(y, 3000.0, sr) / tone_at(y, 1000.0, sr)
+        };
+        let (a, b) = (thd(44100.0), thd(96000.0));
+        assert!(a > 1e-3 && (a / b - 1.0).abs() < 0.05, "{} {}", a, b);
+    }
+
+    #[test]
+    fn locked_parameters_ignore_the_host() {
+        let p = EffectParameters::default();
+        p.lock(0);
+        assert!(p.is_locked(0) && !p.is_locked(1));
+        let before = p.get_parameter(0);
+        p.set_parameter(0, 0.9);
+        p.set_parameter(1, 0.9);
+        assert_eq!(p.get_parameter(0), before);
+        assert_eq!(p.get_parameter(1), 0.9);
+        // the typed setters still reach it
+        p.lock(26);
+        p.set_material(TapeMaterial::Nickel);
+        assert_eq!(p.material(), TapeMaterial::Nickel);
+        p.unlock(0);
+        p.set_parameter(0, 0.9);
+        assert_eq!(p.get_parameter(0), 0.9);
+        // out of range indexes are never locked
+        p.lock(-1);
+        p.lock(NUM_PARAMS);
+        assert!(!p.is_locked(-1) && !p.is_locked(NUM_PARAMS));
+    }
+
+    #[test]
+    fn null_calibration_finds_a_pure_delay() {
+        let e = effect();
+        e.params.set_parameter(3, 0.0);
+        e.params.set_oversampling(OversamplingFactor::X4);
+        e.params.set_parameter(10, 1.0);
+        let (delay, gain) = e.null_calibration();
+        assert_eq!(delay, e.latency());
+        assert!(delay > 0 && (gain - 1.0).abs() < 1e-9, "{}", gain);
+    }
+
+    #[test]
+    fn meter_modes_have_their_ballistics() {
+        let burst = |mode: MeterMode| {
+            let mut e = effect();
+            e.params.set_parameter(3, 0.0);
+            e.params.set_meter_mode(mode);
+            run(&mut e, &[(0.0, 0.0); 64]);
+            run(&mut e, &vec![(0.5, 0.5); (0.005 * SR) as usize]);
+            let read = e.peak_hold.get();
+            run(&mut e, &vec![(0.0, 0.0); (1.5 * SR) as usize]);
+            (read, e.peak_hold.get())
+        };
+        let (peak, _) = burst(MeterMode::Peak);
+        let (ppm, ppm_fall) = burst(MeterMode::Ppm);
+        let (vu, _) = burst(MeterMode::Vu);
+        assert!((peak - 0.5).abs() < 1e-9);
+        // a 5 ms burst reads 2 dB low on the PPM, much lower on the VU
+        assert!((20.0 * (ppm / 0.5).log10() + 2.0).abs() < 0.25);
+        assert!(vu < 0.25 * ppm);
+        // and the PPM falls 20 dB in 1.5 s
+        assert!((20.0 * (ppm_fall / ppm).log10() + 20.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn only_the_band_is_saturated() {
+        // 1 kHz to 5 kHz
+        let knob = |hz: f64| ((hz / BAND_MIN).ln() / (BAND_MAX / BAND_MIN).ln()) as f32;
+        let heard = |freq: f64, band: bool| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            if band {
+                e.params.set_parameter(39, knob(1000.0));
+                e.params.set_parameter(40, knob(5000.0));
+            }
+            let y = run(&mut e, &sine(freq, 0.5, 22050));
+            let y = &y[22050 - 4410..];
+            (tone(y, freq), tone(y, 3.0 * freq) / tone(y, freq))
+        };
+        // a tone below the band goes around the saturator
+        let (level, thd) = heard(100.0, true);
+        assert!((level - 0.5).abs() < 0.01 && thd < 1e-3, "{} {}", level, thd);
+        assert!(heard(100.0, false).1 > 10.0 * thd);
+        // a tone in the band is saturated like without the band
+        let (inside, full) = (heard(2000.0, true).1, heard(2000.0, false).1);
+        assert!(inside > 0.5 * full && inside > 10.0 * thd, "{} {}", inside, full);
+    }
+
+    #[test]
+    fn latency_is_reported_when_resuming() {
+        let mut e = effect();
+        e.resume();
+        assert_eq!(e.reported_latency, 0);
+        e.params.set_parameter(10, 1.0);
+        // the audio thread leaves the host alone
+        run(&mut e, &[(0.0, 0.0); 64]);
+        assert_eq!(e.reported_latency, 0);
+        e.suspend();
+        e.resume();
+        assert_eq!(e.reported_latency, e.latency());
+        assert!(e.reported_latency > 0);
+    }
+
+    #[test]
+    fn material_switch_is_click_free() {
+        let x = sine(50.0, 0.8, 8820);
+        let steps = |y: &[(f64, f64)]| {
+            y.windows(2).fold(0.0f64, |m, w| m.max((w[1].0 - w[0].0).abs()))
+        };
+        let mut e = effect();
+        e.params.set_parameter(0, 1.0);
+        e.params.set_material(TapeMaterial::Tungsten);
+        let before = run(&mut e, &x);
+        e.params.set_material(TapeMaterial::Magnetite);
+        assert_eq!(e.params.get_parameter_text(26), "magnetite");
+        let after = run(&mut e, &x);
+
+        // the curves differ, but the switch itself moves the output no
+        // further than the sine does from one sample to the next
+        let joint = [before[before.len() - 1], after[0]];
+        let steady = steps(&before[4410..]);
+        assert!(steps(&joint) <= 1.5 * steady);
+        assert!(steps(&after) <= 1.5 * steady);
+        assert!(before[4410..].iter().zip(after[4410..].iter()).any(|(a, b)| (a.0 - b.0).abs() > 1e-3));
+    }
+
+    #[test]
+    fn hiss_is_off_at_the_bottom_of_the_knob() {
+        let silence = vec![(0.0, 0.0); 44100];
+        let mut e = effect();
+        assert_eq!(e.params.get_parameter_text(45), "-inf dB");
+        assert!(run(&mut e, &silence).iter().all(|s| s.0 == 0.0 && s.1 == 0.0));
+
+        // at the top it sits around its level, whatever the signal
+        e.params.set_parameter(45, 1.0);
+        assert_eq!(e.params.get_parameter_text(45), "-30.0 dB");
+        let y = run(&mut e, &silence);
+        let rms = (y[4410..].iter().map(|s| s.0 * s.0).sum::<f64>() / 39690.0).sqrt();
+        let db = 20.0 * rms.log10();
+        assert!(db > HISS_MAX - 6.0 && db < HISS_MAX + 12.0, "{} dB", db);
+    }
+
+    #[test]
+    fn head_eq_follows_the_tape_speed() {
+        // gain of the head EQ at a frequency, for a signal too quiet to
+        // saturate, so the emphasis cancels
+        let gain = |speed: TapeSpeed, freq: f64| {
+            let through = |on: bool| {
+                let mut e = clean(OversamplingFactor::X1, 1.0);
+                e.params.set_parameter(46, if on { 1.0 } else { 0.0 });
+                e.params.set_tape_speed(speed);
+                tone(&run(&mut e, &sine(freq, 0.01, 13230))[4410..], freq)
+            };
+            20.0 * (through(true) / through(false)).log10()
+        };
+        for &speed in TapeSpeed::ALL.iter() {
+            assert!(gain(speed, 1000.0).abs() < 0.5, "{:?}", speed);
+            assert!((gain(speed, speed.bump()) - BUMP_GAIN).abs() < 0.5, "{:?}", speed);
+        }
+        // the slower the tape, the sooner the highs roll off
+        let highs: Vec<f64> = TapeSpeed::ALL.iter().map(|&s| gain(s, 16000.0)).collect();
+        assert!(highs[0] < highs[1] - 1.0 && highs[1] < highs[2] && highs[2] < 0.0, "{:?}", highs);
+    }
+
+    #[test]
+    fn programs_load_the_factory_presets() {
+        let p = EffectParameters::default();
+        for (n, preset) in presets::FACTORY.iter().enumerate() {
+            p.set_parameter(9, 1.0);
+            p.change_preset(n as i32);
+            assert_eq!(p.get_preset_num(), n as i32);
+            assert_eq!(p.get_preset_name(n as i32), preset.name);
+            // the preset's values on top of the defaults
+            for i in 0..NUM_PARAMS {
+                let value = preset.values.iter().find(|v| v.0 == i).map_or(DEFAULTS[i as usize], |v| v.1);
+                assert_eq!(p.get_parameter(i), value, "{} {}", preset.name, i);
+            }
+        }
+        // out of range programs change nothing
+        p.change_preset(presets::FACTORY.len() as i32);
+        assert_eq!(p.get_preset_num(), presets::FACTORY.len() as i32 - 1);
+        assert_eq!(p.get_preset_name(-1), "");
+    }
+
+    #[test]
+    fn preset_data_round_trips_and_survives_new_parameters() {
+        let p = EffectParameters::default();
+        p.change_preset(2);
+        let data = p.get_preset_data();
+        let q = EffectParameters::default();
+        q.load_bank_data(&p.get_bank_data());
+        assert_eq!(q.values(), p.values());
+
+        // a blob from a version with fewer parameters leaves the newer ones
+        let old = code::to_bytes(&p.values()[..10]);
+        let q = EffectParameters::default();
+        q.set_parameter(20, 1.0);
+        q.load_preset_data(&old);
+        assert_eq!(q.values()[..10], p.values()[..10]);
+        assert_eq!(q.get_parameter(20), 1.0);
+
+        // a malformed one is ignored
+        q.load_preset_data(&data[..data.len() - 1]);
+        assert_eq!(q.get_parameter(20), 1.0);
+    }
+
+    #[test]
+    fn mid_side_and_link_share_the_drive() {
+        let heard = |mode: StereoMode, link: f32, x: &[(f64, f64)]| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_stereo_mode(mode);
+            e.params.set_parameter(48, link);
+            run(&mut e, x)
+        };
+
+        // mid/side leaves a centered signal centered and an out of phase one
+        // out of phase, but drives the mid of a centered one harder
+        let x = sine(200.0, 0.8, 4410);
+        let ms = heard(StereoMode::MidSide, 0.0, &x);
+        assert!(ms.iter().all(|s| (s.0 - s.1).abs() < 1e-12));
+        let lr = heard(StereoMode::Independent, 0.0, &x);
+        assert!(ms.iter().zip(lr.iter()).any(|(a, b)| (a.0 - b.0).abs() > 1e-3));
+        let wide: Vec<(f64, f64)> = x.iter().map(|s| (s.0, -s.0)).collect();
+        assert!(heard(StereoMode::MidSide, 0.0, &wide).iter().all(|s| (s.0 + s.1).abs() < 1e-12));
+
+        // the link bleeds the distortion of one channel into the other, all
+        // the way linked the channels run like the linked mode
+        let left: Vec<(f64, f64)> = x.iter().map(|s| (s.0, 0.0)).collect();
+        let bleed = |link: f32| peak(&heard(StereoMode::Independent, link, &left)
+            .iter().map(|s| (0.0, s.1)).collect::<Vec<_>>());
+        assert_eq!(bleed(0.0), 0.0);
+        assert!(bleed(0.5) > 1e-3 && bleed(0.5) < bleed(1.0));
+        assert_eq!(heard(StereoMode::Independent, 1.0, &left), heard(StereoMode::Linked, 0.0, &left));
+    }
+
+    /// the output of a 100 Hz sine, settled, with bias and under-bias
+    fn biased(bias: f32, xover: f32, amp: f64) -> Vec<(f64, f64)> {
+        let mut e = effect();
+        e.params.set_parameter(49, bias);
+        e.params.set_parameter(50, xover);
+        run(&mut e, &sine(100.0, amp, 44100))[22050..].to_vec()
+    }
+
+    #[test]
+    fn bias_adds_even_harmonics() {
+        // shifting the operating point makes the curve asymmetric, and the
+        // DC it causes is taken off again
+        let x = biased(0.0, 0.0, 0.5);
+        let y = biased(1.0, 0.0, 0.5);
+        assert!(tone(&x, 200.0) < 1e-4 * tone(&x, 100.0));
+        assert!(tone(&y, 200.0) > 1e-2 * tone(&y, 100.0));
+        let dc = y.iter().map(|s| s.0).sum::<f64>() / y.len() as f64;
+        assert!(dc.abs() < 1e-2, "{}", dc);
+    }
+
+    #[test]
+    fn under_bias_swallows_quiet_signals() {
+        let loss = |amp: f64| tone(&biased(0.0, 1.0, amp), 100.0) / tone(&biased(0.0, 0.0, amp), 100.0);
+        assert!(loss(0.01) < 0.5 && loss(0.5) > 0.8);
+    }
+
+    #[test]
+    fn loop_curve_is_one_closed_cycle() {
+        let p = EffectParameters::default();
+        let curve = p.loop_curve();
+        let (first, last) = (curve[0], curve[curve.len() - 1]);
+        assert_eq!(first.0, last.0);
+        assert!((first.1 - last.1).abs() < 1e-3);
+        // the way down lies apart from the way up
+        let up = curve[LOOP_STEPS / 2].1;
+        let down = curve[3 * LOOP_STEPS / 2].1;
+        assert_eq!(curve[LOOP_STEPS / 2].0, curve[3 * LOOP_STEPS / 2].0);
+        assert!((down - up).abs() > 1e-3);
+        // and the shape follows the squareness
+        p.set_parameter(1, 1.0);
+        assert_ne!(p.loop_curve(), curve);
+    }
+
+    #[test]
+    fn dropouts_hit_the_channels_apart() {
+        let mut e = effect();
+        e.params.set_parameter(51, 1.0);
+        e.params.set_parameter(52, 1.0);
+        let y = run(&mut e, &sine(1000.0, 0.1, 441000));
+        // level of each 10 ms slice, a dip shows as a slice well below the
+        // level of the sine
+        let dips = |ch: fn(&(f64, f64)) -> f64| -> Vec<bool> {
+            y.chunks(441).map(|c| c.iter().fold(0.0, |p: f64, s| p.max(ch(s).abs())) < 0.05).collect()
+        };
+        let (l, r) = (dips(|s| s.0), dips(|s| s.1));
+        assert!(l.iter().any(|&d| d) && r.iter().any(|&d| d));
+        assert_ne!(l, r);
+        assert_eq!(e.params.get_parameter_text(51), "2.00 /s");
+    }
+
+    #[test]
+    fn wet_match_levels_the_wet_with_the_dry() {
+        let x = sine(1000.0, 0.5, 4 * 44100);
+        let rms = |x: &[(f64, f64)]| (x.iter().map(|s| s.0 * s.0).sum::<f64>() / x.len() as f64).sqrt();
+        let tail = |y: &[(f64, f64)]| 20.0 * (rms(&y[y.len() - 22050..]) / rms(&x[x.len() - 22050..])).log10();
+        let wet = |matched: f32, pre: f32| {
+            let mut e = effect();
+            e.params.set_parameter(0, pre);
+            e.params.set_parameter(53, matched);
+            run(&mut e, &x)
+        };
+        for &pre in [0.8, 1.0].iter() {
+            assert!(tail(&wet(0.0, pre)).abs() > 3.0, "{}", pre);
+            assert!(tail(&wet(1.0, pre)).abs() < 0.5, "{}", pre);
+        }
+
+        // nothing is trimmed beyond MATCH_MAX, however quiet the wet signal
+        let mut e = effect();
+        e.params.set_parameter(53, 1.0);
+        e.params.set_parameter(3, 1.0);
+        e.params.set_parameter(50, 1.0);
+        let y = run(&mut e, &sine(1000.0, 0.001, 44100));
+        assert!(peak(&y) <= 0.001 * MATCH_MAX);
+        assert_eq!(e.params.get_parameter_text(53), "on");
+    }
+
+    #[test]
+    fn transport_center_is_read_once_per_block() {
+        let mut e = effect();
+        run(&mut e, &[(0.0, 0.0); 16]);
+        assert_eq!(e.block.tp_len, 0);
+        // turning the wow on mid-block waits for the next block
+        e.params.set_parameter(41, 0.5);
+        e.process_sample(0.0, 0.0);
+        assert_eq!(e.block.tp_len, 0);
+        run(&mut e, &[(0.0, 0.0); 16]);
+        assert_eq!(e.block.tp_len, e.transport.latency());
+        assert!(e.block.tp_len > 0);
+    }
+
+    /// run a block of channels through the plugin the way the host does,
+    /// as many as it passes in and out
+    fn process_channels(e: &mut Effect, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let samples = input[0].len();
+        let mut output = vec![vec![0.0; samples]; input.len()];
+        let inputs: Vec<*const f32> = input.iter().map(|c| c.as_ptr()).collect();
+        let mut outputs: Vec<*mut f32> = output.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let mut buffer = unsafe {
+            AudioBuffer::from_raw(inputs.len(), outputs.len(), inputs.as_ptr(), outputs.as_mut_ptr(), samples)
+        };
+        e.process(&mut buffer);
+        output
+    }
+
+    #[test]
+    fn mono_runs_through_a_single_model() {
+        let mono: Vec<f32> = sine(200.0, 0.8, 4410).iter().map(|s| s.0 as f32).collect();
+        let x: Vec<(f64, f64)> = mono.iter().map(|&s| (s as f64, s as f64)).collect();
+        let heard = |mode: StereoMode| {
+            let e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_stereo_mode(mode);
+            e
+        };
+        // whatever the stereo mode, a single channel sounds like the linked
+        // mode, which only steps the left model
+        let linked: Vec<f32> = run(&mut heard(StereoMode::Linked), &x).iter().map(|s| s.0 as f32).collect();
+        for &mode in StereoMode::ALL.iter() {
+            let y = process_channels(&mut heard(mode), std::slice::from_ref(&mono));
+            assert_eq!(y[0], linked, "{:?}", mode);
+        }
+        assert_ne!(run(&mut heard(StereoMode::MidSide), &x), run(&mut heard(StereoMode::Linked), &x));
+    }
+
+    #[test]
+    fn bus_takes_every_channel_the_host_passes() {
+        let mut e = effect();
+        assert_eq!((e.get_info().inputs, e.get_info().outputs), (MAX_CHANNELS as i32, MAX_CHANNELS as i32));
+        let x: Vec<f32> = sine(200.0, 0.8, 4410).iter().map(|s| s.0 as f32).collect();
+        let stereo = process_channels(&mut effect(), &[x.clone(), x.clone()]);
+
+        // before the host resumes the plugin only the first pair runs
+        let y = process_channels(&mut e, &vec![x.clone(); 3]);
+        assert_eq!(y[..2], stereo[..]);
+        assert_eq!(y[2], x);
+
+        // then every pair runs through a chain of its own, and so does the
+        // odd channel out
+        e.suspend();
+        e.resume();
+        assert_eq!(e.buses.len(), MAX_CHANNELS / 2 - 1);
+        assert!(e.buses.iter().all(|b| !b.live && b.sr == e.sr));
+        for channels in [1, 2, 5, MAX_CHANNELS] {
+            e.suspend();
+            let y = process_channels(&mut e, &vec![x.clone(); channels]);
+            assert_eq!(y.len(), channels);
+            for (ch, y) in y.iter().enumerate() {
+                assert!(y.iter().all(|s| s.is_finite()));
+                assert!(y.iter().zip(x.iter()).any(|(a, b)| (a - b).abs() > 1e-3), "{} {}", channels, ch);
+            }
+            if channels > 1 {
+                for pair in y.chunks(2).filter(|p| p.len() == 2) {
+                    let err = pair.iter().zip(stereo.iter())
+                        .flat_map(|(a, b)| a.iter().zip(b.iter()))
+                        .fold(0.0, |p: f32, (a, b)| p.max((a - b).abs()));
+                    assert!(err < 1e-3, "{} {}", channels, err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn safety_clip_has_its_own_ceiling() {
+        let mut e = effect();
+        e.params.set_parameter(3, 0.0);
+        e.params.set_parameter(54, 1.0);
+        e.params.set_parameter(55, 0.75);
+        assert_eq!(e.params.get_parameter_text(55), "-6.0 dBFS");
+        assert!(!e.params.is_transparent());
+        // the auto level ceiling stays out of it
+        e.params.set_parameter(7, 0.0);
+        let y = run(&mut e, &sine(1000.0, 2.0, 4410));
+        let ceiling = db_to_gain(-6.0);
+        assert!(peak(&y[2205..]) <= ceiling && peak(&y[2205..]) > 0.95 * ceiling);
+        e.params.set_parameter(54, 0.0);
+        assert!(e.params.is_transparent());
+    }
+
+    #[test]
+    fn suspend_clears_the_state() {
+        let tail = |suspend: bool| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(4, 1.0);
+            e.params.set_parameter(41, 0.5);
+            e.resume();
+            run(&mut e, &sine(100.0, 1.0, 4410));
+            if suspend {
+                e.suspend();
+                e.resume();
+            }
+            peak(&run(&mut e, &[(0.0, 0.0); 4410]))
+        };
+        // what rings on from the loud part would thump on the way back in
+        assert!(tail(false) > 1e-3);
+        assert_eq!(tail(true), 0.0);
+    }
+
+    #[test]
+    fn rhythmic_noise_pulses_with_the_beat() {
+        let hissy = |rhythmic: f32| {
+            let e = effect();
+            e.params.set_parameter(45, 1.0);
+            e.params.set_parameter(56, rhythmic);
+            e.params.set_parameter(57, 1.0);
+            e
+        };
+        // without a tempo from the host the noise is steady
+        let silence = vec![(0.0, 0.0); 88200];
+        assert_eq!(run(&mut hissy(0.0), &silence), run(&mut hissy(1.0), &silence));
+
+        // at 120 bpm a beat is 22050 samples, 50 windows of 441 for the level
+        let mut e = hissy(1.0);
+        e.follow_beat(Some((0.0, 120.0)));
+        let y = run(&mut e, &silence);
+        let level: Vec<f64> = y.chunks(441).map(|c| c.iter().map(|s| s.0 * s.0).sum::<f64>()).collect();
+        let mean = level.iter().sum::<f64>() / level.len() as f64;
+        let similarity = |lag: usize| -> f64 {
+            level.iter().zip(level[lag..].iter()).map(|(a, b)| (a - mean) * (b - mean)).sum()
+        };
+        let period = (25..=75).max_by(|&a, &b| similarity(a).partial_cmp(&similarity(b)).unwrap()).unwrap();
+        assert!((49..=51).contains(&period), "{}", period);
+        // loudest on the beat, quietest between beats
+        assert!(level[0] > 4.0 * level[25]);
+        assert_eq!(e.params.get_parameter_text(56), "on");
+    }
+
+    #[test]
+    fn reset_modulation_leaves_the_saturation_alone() {
+        // 25 periods of 100 Hz, so it can be run over and over
+        let x = sine(100.0, 0.8, 11025);
+        let twin = |wow: f32| {
+            let mut e = effect();
+            e.params.set_parameter(0, 1.0);
+            e.params.set_parameter(41, wow);
+            run(&mut e, &x);
+            e
+        };
+
+        // with no modulation running the output carries on exactly, the
+        // hysteresis and filters keep their state
+        let (mut a, mut b) = (twin(0.0), twin(0.0));
+        b.params.reset_modulation();
+        assert_eq!(run(&mut a, &x), run(&mut b, &x));
+        assert!(!b.params.resync_pending.load(Ordering::Relaxed));
+        b.reset_state();
+        assert_ne!(run(&mut a, &x), run(&mut b, &x));
+
+        // with wow the cycle starts over, without a jump in the output
+        let (mut a, mut b) = (twin(1.0), twin(1.0));
+        b.params.reset_modulation();
+        let (ya, yb) = (run(&mut a, &x), run(&mut b, &x));
+        assert_ne!(ya, yb);
+        let step = |y: &[(f64, f64)]| y.windows(2).map(|w| (w[1].0 - w[0].0).abs()).fold(0.0, f64::max);
+        assert!(step(&yb) < 1.1 * step(&ya), "{} {}", step(&yb), step(&ya));
+    }
+
+    #[test]
+    fn noise_gate_fades_the_hiss_out_in_the_pauses() {
+        let hissy = |hiss: f32, gate: f32| {
+            let e = effect();
+            e.params.set_parameter(45, hiss);
+            e.params.set_parameter(60, gate);
+            e
+        };
+        // a second of signal at -20 dB, then two of silence
+        let mut x = sine(1000.0, 0.1, 44100);
+        x.extend(vec![(0.0, 0.0); 88200]);
+        // the hiss alone, what it adds to the output of the chain without it
+        let clean = run(&mut hissy(0.0, 0.0), &x);
+        let hiss = |y: Vec<(f64, f64)>| -> Vec<f64> {
+            y.iter().zip(clean.iter()).map(|(y, c)| y.0 - c.0).collect()
+        };
+        let open = hiss(run(&mut hissy(1.0, 0.0), &x));
+        // the gate at -50 dBFS
+        let mut e = hissy(1.0, 0.5);
+        let gated = hiss(run(&mut e, &x));
+        assert_eq!(e.params.get_parameter_text(60), "-50.0 dBFS");
+
+        // under the signal the hiss is all there, the same as without the
+        // gate once it has opened
+        let err = open[4410..44100].iter().zip(gated[4410..44100].iter())
+            .fold(0.0, |p: f64, (a, b)| p.max((a - b).abs()));
+        assert!(err < 1e-9, "{}", err);
+        // in the pause it fades out, while without the gate it plays on
+        let power = |y: &[f64]| y.iter().map(|y| y * y).sum::<f64>() / y.len() as f64;
+        let pause = power(&open[88200..]);
+        assert!(pause > 1e-7);
+        assert!(power(&gated[88200..]) < 1e-4 * pause);
+    }
+}
@@ -0,0 +1,97 @@
+// Lightweight loudness estimation, loosely following ITU-R BS.1770.
+
+use crate::envelope::time_coeff;
+use crate::filters::Biquad;
+
+// K-weighting, a high shelf for the head and a high-pass for the low end
+const SHELF_FREQ: f64 = 1681.0;
+const SHELF_GAIN: f64 = 4.0;
+const HPF_FREQ: f64 = 38.0;
+// integration time of the short-term estimate, in seconds
+const INTEGRATION: f64 = 3.0;
+// integration time of the plain mean square, in seconds
+const MS_WINDOW: f64 = 0.3;
+
+/// short-term K-weighted mean square of a stereo signal
+pub struct Loudness {
+    shelf_l: Biquad,
+    shelf_r: Biquad,
+    hpf_l: Biquad,
+    hpf_r: Biquad,
+    coeff: f64,
+    ms: f64,    // running mean square
+}
+
+impl Loudness {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        let mut l = Loudness {
+            shelf_l: Biquad::new(),
+            shelf_r: Biquad::new(),
+            hpf_l: Biquad::new(),
+            hpf_r: Biquad::new(),
+            coeff: 1.0,
+            ms: 0.0,
+        };
+        l.set_rate(rate);
+        l
+    }
+
+    /// recompute the weighting filters for a new sample rate
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        for f in [&mut self.shelf_l, &mut self.shelf_r].iter_mut() {
+            f.set_high_shelf(SHELF_FREQ, 0.707, SHELF_GAIN, rate);
+        }
+        for f in [&mut self.hpf_l, &mut self.hpf_r].iter_mut() {
+            f.set_highpass(HPF_FREQ, 0.5, rate);
+        }
+        self.coeff = time_coeff(INTEGRATION, rate);
+    }
+
+    /// advance by one stereo sample, returns the running mean square, which
+    /// is proportional to the loudness in the linear power domain
+    pub fn step(&mut self, l: f64, r: f64) -> f64 {
+        let l = self.hpf_l.step(self.shelf_l.step(l));
+        let r = self.hpf_r.step(self.shelf_r.step(r));
+        self.ms += self.coeff * (l * l + r * r - self.ms);
+        self.ms
+    }
+
+    pub fn reset(&mut self) {
+        self.shelf_l.reset();
+        self.shelf_r.reset();
+        self.hpf_l.reset();
+        self.hpf_r.reset();
+        self.ms = 0.0;
+    }
+}
+
+/// unweighted running mean square of a stereo signal over a short window,
+/// quicker to follow than the loudness estimate
+pub struct MeanSquare {
+    coeff: f64,
+    ms: f64,
+}
+
+impl MeanSquare {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        MeanSquare { coeff: time_coeff(MS_WINDOW, rate), ms: 0.0 }
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.coeff = time_coeff(MS_WINDOW, rate);
+    }
+
+    /// advance by one stereo sample, returns the running mean square
+    pub fn step(&mut self, l: f64, r: f64) -> f64 {
+        self.ms += self.coeff * (l * l + r * r - self.ms);
+        self.ms
+    }
+
+    pub fn reset(&mut self) {
+        self.ms = 0.0;
+    }
+}
@@ -0,0 +1,93 @@
+// Output metering.
+
+use std::f64::consts::PI;
+
+// interpolation of the true-peak estimate, 4x like ITU-R BS.1770
+const PHASES: usize = 4;
+// per phase, odd so the first phase lands on the samples themselves
+const TAPS: usize = 13;
+
+/// true-peak estimator, interpolates the signal 4x with a windowed sinc and
+/// returns the highest magnitude among the original and interpolated points
+pub struct TruePeak {
+    coeffs: [[f64; TAPS]; PHASES],
+    hist: [f64; TAPS],  // most recent sample first
+}
+
+impl TruePeak {
+    pub fn new() -> Self {
+        // the sinc is centered on a sample, which makes the first phase the
+        // identity, the window spans the taps either side of it
+        let center = (PHASES * (TAPS - 1) / 2) as f64;
+        let half = (TAPS + 1) as f64 / 2.0;
+        let mut coeffs = [[0.0; TAPS]; PHASES];
+        for (p, phase) in coeffs.iter_mut().enumerate() {
+            for (k, c) in phase.iter_mut().enumerate() {
+                let n = (p + k * PHASES) as f64;
+                let t = (n - center) / PHASES as f64;
+                let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+                let window = 0.5 + 0.5 * (PI * t / half).cos();
+                *c = sinc * window;
+            }
+            // each phase passes DC at unity
+            let sum: f64 = phase.iter().sum();
+            for c in phase.iter_mut() {
+                *c /= sum;
+            }
+        }
+        TruePeak { coeffs, hist: [0.0; TAPS] }
+    }
+
+    /// push a sample, returns the true-peak estimate around it
+    pub fn step(&mut self, x: f64) -> f64 {
+        self.hist.rotate_right(1);
+        self.hist[0] = x;
+        let mut peak: f64 = 0.0;
+        for phase in self.coeffs.iter() {
+            let y: f64 = phase.iter().zip(self.hist.iter()).map(|(c, h)| c * h).sum();
+            peak = peak.max(y.abs());
+        }
+        peak
+    }
+
+    pub fn reset(&mut self) {
+        self.hist = [0.0; TAPS];
+    }
+}
+
+impl Default for TruePeak {
+    fn default() -> Self {
+        TruePeak::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// highest estimate over a signal
+    fn measure(x: &[f64]) -> f64 {
+        let mut tp = TruePeak::new();
+        x.iter().map(|&x| tp.step(x)).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn samples_read_at_their_own_level() {
+        let mut impulse = vec![0.0; 2 * TAPS];
+        impulse[0] = 1.0;
+        assert!((measure(&impulse) - 1.0).abs() < 1e-12);
+        // once the filter is full of it, a constant reads as itself
+        let mut tp = TruePeak::new();
+        let dc = (0..2 * TAPS).map(|_| tp.step(0.5)).last().unwrap();
+        assert!((dc - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn peaks_between_samples_are_found() {
+        // a quarter of the sample rate, sampled 45 degrees off its peaks
+        let x: Vec<f64> = (0..256).map(|n| (0.5 * PI * n as f64 + 0.25 * PI).sin()).collect();
+        let sample_peak = x.iter().fold(0.0, |p: f64, x| p.max(x.abs()));
+        assert!(sample_peak < 0.71);
+        assert!((measure(&x) - 1.0).abs() < 0.03);
+    }
+}
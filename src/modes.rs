@@ -0,0 +1,387 @@
+// Typed modes, for driving the plugin from code instead of through the 0 - 1
+// host parameters. Each mode converts from a parameter value, which is how
+// the processing reads it, and back to one, which is how the setters store it.
+
+/// magnetic material of the saturation stage, from the hardest curve to the
+/// softest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TapeMaterial {
+    Tungsten,
+    Steel,
+    Iron,
+    Nickel,
+    Magnetite,
+}
+
+impl TapeMaterial {
+    /// all materials, in parameter order
+    pub const ALL: [TapeMaterial; 5] = [
+        TapeMaterial::Tungsten,
+        TapeMaterial::Steel,
+        TapeMaterial::Iron,
+        TapeMaterial::Nickel,
+        TapeMaterial::Magnetite,
+    ];
+
+    /// material of a parameter value, five equal buckets. A value exactly on
+    /// a boundary (0.2, 0.4, ...) belongs to the bucket above it, so it never
+    /// flickers between two materials.
+    pub fn from_param(value: f32) -> Self {
+        let n = Self::ALL.len();
+        Self::ALL[((value.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1)]
+    }
+
+    /// parameter value at the center of the material's bucket
+    pub fn to_param(self) -> f32 {
+        (self as usize as f32 + 0.5) / Self::ALL.len() as f32
+    }
+
+    /// display name
+    pub fn name(self) -> &'static str {
+        match self {
+            TapeMaterial::Tungsten => "tungsten",
+            TapeMaterial::Steel => "steel",
+            TapeMaterial::Iron => "iron",
+            TapeMaterial::Nickel => "nickel",
+            TapeMaterial::Magnetite => "magnetite",
+        }
+    }
+}
+
+/// input coupling of the saturator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coupling {
+    /// DC is removed before the saturator
+    AC,
+    /// offsets reach the saturator
+    DC,
+}
+
+impl Coupling {
+    pub fn from_param(value: f32) -> Self {
+        if value > 0.5 { Coupling::DC } else { Coupling::AC }
+    }
+
+    pub fn to_param(self) -> f32 {
+        match self {
+            Coupling::AC => 0.0,
+            Coupling::DC => 1.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Coupling::AC => "AC",
+            Coupling::DC => "DC",
+        }
+    }
+}
+
+/// dry/wet crossfade law
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MixLaw {
+    Linear,
+    /// sin/cos law, keeps the power constant for uncorrelated signals
+    EqualPower,
+}
+
+impl MixLaw {
+    pub fn from_param(value: f32) -> Self {
+        if value > 0.5 { MixLaw::EqualPower } else { MixLaw::Linear }
+    }
+
+    pub fn to_param(self) -> f32 {
+        match self {
+            MixLaw::Linear => 0.0,
+            MixLaw::EqualPower => 1.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MixLaw::Linear => "linear",
+            MixLaw::EqualPower => "equal power",
+        }
+    }
+}
+
+/// oversampling of the nonlinear stage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OversamplingFactor {
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+impl OversamplingFactor {
+    /// all factors, in parameter order
+    pub const ALL: [OversamplingFactor; 4] = [
+        OversamplingFactor::X1,
+        OversamplingFactor::X2,
+        OversamplingFactor::X4,
+        OversamplingFactor::X8,
+    ];
+
+    /// factor of a parameter value, four equal buckets, a boundary belongs to
+    /// the bucket above it
+    pub fn from_param(value: f32) -> Self {
+        let n = Self::ALL.len();
+        Self::ALL[((value.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1)]
+    }
+
+    /// parameter value at the center of the factor's bucket
+    pub fn to_param(self) -> f32 {
+        (self as usize as f32 + 0.5) / Self::ALL.len() as f32
+    }
+
+    /// number of 2x stages
+    pub fn stages(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OversamplingFactor::X1 => "off",
+            OversamplingFactor::X2 => "2x",
+            OversamplingFactor::X4 => "4x",
+            OversamplingFactor::X8 => "8x",
+        }
+    }
+}
+
+/// how the two channels go through the hysteresis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoMode {
+    /// each channel has its own model
+    Independent,
+    /// one model runs on the average of the channels and its distortion is
+    /// added to both, so the image stays put
+    Linked,
+    /// the models run on mid and side instead of left and right
+    MidSide,
+}
+
+impl StereoMode {
+    /// all modes, in parameter order
+    pub const ALL: [StereoMode; 3] = [
+        StereoMode::Independent,
+        StereoMode::Linked,
+        StereoMode::MidSide,
+    ];
+
+    /// mode of a parameter value, three equal buckets, a boundary belongs to
+    /// the bucket above it
+    pub fn from_param(value: f32) -> Self {
+        let n = Self::ALL.len();
+        Self::ALL[((value.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1)]
+    }
+
+    /// parameter value at the center of the mode's bucket
+    pub fn to_param(self) -> f32 {
+        (self as usize as f32 + 0.5) / Self::ALL.len() as f32
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StereoMode::Independent => "independent",
+            StereoMode::Linked => "linked",
+            StereoMode::MidSide => "mid/side",
+        }
+    }
+}
+
+/// character of the artifacts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Medium {
+    /// splice clicks at random times
+    Tape,
+    /// on top of the splice clicks, a scratch once per revolution, rumble and
+    /// wow from an off-center hole
+    Vinyl,
+}
+
+impl Medium {
+    pub fn from_param(value: f32) -> Self {
+        if value > 0.5 { Medium::Vinyl } else { Medium::Tape }
+    }
+
+    pub fn to_param(self) -> f32 {
+        match self {
+            Medium::Tape => 0.0,
+            Medium::Vinyl => 1.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Medium::Tape => "tape",
+            Medium::Vinyl => "vinyl",
+        }
+    }
+}
+
+/// ballistics of the sample-peak meter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeterMode {
+    /// instant attack and a slow release, shows every peak
+    Peak,
+    /// quasi-peak meter (IEC 60268-10 type I), reads a 5 ms burst 2 dB low
+    /// and falls 20 dB in 1.5 s
+    Ppm,
+    /// volume unit meter, an average that takes 300 ms to settle either way
+    Vu,
+}
+
+impl MeterMode {
+    /// all modes, in parameter order
+    pub const ALL: [MeterMode; 3] = [MeterMode::Peak, MeterMode::Ppm, MeterMode::Vu];
+
+    /// mode of a parameter value, three equal buckets, a boundary belongs to
+    /// the bucket above it
+    pub fn from_param(value: f32) -> Self {
+        let n = Self::ALL.len();
+        Self::ALL[((value.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1)]
+    }
+
+    /// parameter value at the center of the mode's bucket
+    pub fn to_param(self) -> f32 {
+        (self as usize as f32 + 0.5) / Self::ALL.len() as f32
+    }
+
+    /// attack and release time constants in seconds
+    pub fn times(self) -> (f64, f64) {
+        match self {
+            MeterMode::Peak => (0.0, 1.5),
+            // 1 - exp(-5 ms / attack) = -2 dB, 20 dB = ln(10) release times
+            MeterMode::Ppm => (0.0032, 0.65),
+            // 99% in 300 ms
+            MeterMode::Vu => (0.065, 0.065),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MeterMode::Peak => "peak",
+            MeterMode::Ppm => "PPM",
+            MeterMode::Vu => "VU",
+        }
+    }
+}
+
+/// tape speed of the record/playback head EQ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TapeSpeed {
+    /// 7.5 ips, NAB 90 us emphasis, a low head bump and an early roll-off
+    Ips7,
+    /// 15 ips, NAB 50 us emphasis
+    Ips15,
+    /// 30 ips, AES 17.5 us emphasis, the highest bump and an extended top
+    Ips30,
+}
+
+impl TapeSpeed {
+    /// all speeds, in parameter order
+    pub const ALL: [TapeSpeed; 3] = [TapeSpeed::Ips7, TapeSpeed::Ips15, TapeSpeed::Ips30];
+
+    /// speed of a parameter value, three equal buckets, a boundary belongs to
+    /// the bucket above it
+    pub fn from_param(value: f32) -> Self {
+        let n = Self::ALL.len();
+        Self::ALL[((value.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1)]
+    }
+
+    /// parameter value at the center of the speed's bucket
+    pub fn to_param(self) -> f32 {
+        (self as usize as f32 + 0.5) / Self::ALL.len() as f32
+    }
+
+    /// tape speed in m/s
+    pub fn speed(self) -> f64 {
+        match self {
+            TapeSpeed::Ips7 => 0.1905,
+            TapeSpeed::Ips15 => 0.381,
+            TapeSpeed::Ips30 => 0.762,
+        }
+    }
+
+    /// corner of the emphasis in Hz, from its time constant
+    pub fn emphasis(self) -> f64 {
+        let t = match self {
+            TapeSpeed::Ips7 => 90e-6,
+            TapeSpeed::Ips15 => 50e-6,
+            TapeSpeed::Ips30 => 17.5e-6,
+        };
+        1.0 / (2.0 * std::f64::consts::PI * t)
+    }
+
+    /// frequency of the head bump in Hz, it moves up with the speed
+    pub fn bump(self) -> f64 {
+        match self {
+            TapeSpeed::Ips7 => 30.0,
+            TapeSpeed::Ips15 => 55.0,
+            TapeSpeed::Ips30 => 110.0,
+        }
+    }
+
+    /// cutoff of the playback head roll-off in Hz
+    pub fn rolloff(self) -> f64 {
+        match self {
+            TapeSpeed::Ips7 => 12000.0,
+            TapeSpeed::Ips15 => 18000.0,
+            TapeSpeed::Ips30 => 22000.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TapeSpeed::Ips7 => "7.5 ips",
+            TapeSpeed::Ips15 => "15 ips",
+            TapeSpeed::Ips30 => "30 ips",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip_through_their_parameter() {
+        for &m in TapeMaterial::ALL.iter() {
+            assert_eq!(TapeMaterial::from_param(m.to_param()), m);
+        }
+        for &f in OversamplingFactor::ALL.iter() {
+            assert_eq!(OversamplingFactor::from_param(f.to_param()), f);
+        }
+        for &m in StereoMode::ALL.iter() {
+            assert_eq!(StereoMode::from_param(m.to_param()), m);
+        }
+        for &m in MeterMode::ALL.iter() {
+            assert_eq!(MeterMode::from_param(m.to_param()), m);
+        }
+        for &s in TapeSpeed::ALL.iter() {
+            assert_eq!(TapeSpeed::from_param(s.to_param()), s);
+        }
+        for &c in [Coupling::AC, Coupling::DC].iter() {
+            assert_eq!(Coupling::from_param(c.to_param()), c);
+        }
+        for &l in [MixLaw::Linear, MixLaw::EqualPower].iter() {
+            assert_eq!(MixLaw::from_param(l.to_param()), l);
+        }
+        for &m in [Medium::Tape, Medium::Vinyl].iter() {
+            assert_eq!(Medium::from_param(m.to_param()), m);
+        }
+    }
+
+    #[test]
+    fn boundaries_belong_to_the_bucket_above() {
+        assert_eq!(TapeMaterial::from_param(0.0), TapeMaterial::Tungsten);
+        assert_eq!(TapeMaterial::from_param(0.2), TapeMaterial::Steel);
+        assert_eq!(TapeMaterial::from_param(1.0), TapeMaterial::Magnetite);
+        assert_eq!(OversamplingFactor::from_param(0.5), OversamplingFactor::X4);
+        assert_eq!(StereoMode::from_param(-1.0), StereoMode::Independent);
+        assert_eq!(StereoMode::from_param(2.0), StereoMode::MidSide);
+        assert_eq!(OversamplingFactor::X8.stages(), 3);
+    }
+}
@@ -0,0 +1,170 @@
+// Oversampling of the nonlinear stage, a cascade of 2x halfband stages.
+
+use std::f64::consts::PI;
+
+use crate::delay::DelayLine;
+
+// halfband length, the centered delay of 16 samples at the high rate keeps
+// the latency of every stage a whole number of samples at the base rate
+const TAPS: usize = 33;
+const CENTER: usize = (TAPS - 1) / 2;
+// length of the cheaper economy halfband, centered on the same tap so the
+// latency doesn't depend on which one runs
+const ECONOMY_TAPS: usize = 17;
+const ECONOMY_LO: usize = CENTER - (ECONOMY_TAPS - 1) / 2;
+const ECONOMY_HI: usize = CENTER + (ECONOMY_TAPS - 1) / 2;
+// most 2x stages in the cascade, 8x
+pub const MAX_STAGES: usize = 3;
+
+/// halfband kernel, Blackman windowed sinc with its cutoff at a quarter of
+/// the rate it runs at, centered on CENTER and zero outside of len taps
+fn kernel(len: usize) -> [f64; TAPS] {
+    let half = (len - 1) / 2;
+    let mut coeffs = [0.0; TAPS];
+    for (n, c) in coeffs.iter_mut().enumerate().skip(CENTER - half).take(len) {
+        let t = n as f64 - CENTER as f64;
+        let sinc = if t == 0.0 { 0.5 } else { (0.5 * PI * t).sin() / (PI * t) };
+        let w = PI * (t + half as f64) / half as f64;
+        *c = sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos());
+    }
+    // unity at DC
+    let sum: f64 = coeffs.iter().sum();
+    for c in coeffs.iter_mut() {
+        *c /= sum;
+    }
+    coeffs
+}
+
+/// halfband low-pass, with a full and an economy kernel sharing the history
+struct HalfBand {
+    coeffs: [f64; TAPS],
+    economy: [f64; TAPS],
+    line: DelayLine,
+}
+
+impl HalfBand {
+    fn new() -> Self {
+        HalfBand { coeffs: kernel(TAPS), economy: kernel(ECONOMY_TAPS), line: DelayLine::new(TAPS) }
+    }
+
+    fn push(&mut self, x: f64) {
+        self.line.push(x);
+    }
+
+    fn step(&mut self, x: f64, economy: bool) -> f64 {
+        self.line.push(x);
+        if economy {
+            (ECONOMY_LO..=ECONOMY_HI).map(|i| self.economy[i] * self.line.read(i)).sum()
+        } else {
+            self.coeffs.iter().enumerate().map(|(i, c)| c * self.line.read(i)).sum()
+        }
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+/// runs a nonlinearity at 2x, 4x or 8x the base rate, one channel
+pub struct Oversampler {
+    up: [HalfBand; MAX_STAGES],
+    down: [HalfBand; MAX_STAGES],
+    economy: bool,  // run the shorter kernels
+}
+
+impl Oversampler {
+    pub fn new() -> Self {
+        Oversampler {
+            up: [HalfBand::new(), HalfBand::new(), HalfBand::new()],
+            down: [HalfBand::new(), HalfBand::new(), HalfBand::new()],
+            economy: false,
+        }
+    }
+
+    /// switch to the economy kernels, cheaper but letting more aliasing
+    /// through. The latency stays the same, so this can change at any time.
+    pub fn set_economy(&mut self, economy: bool) {
+        self.economy = economy;
+    }
+
+    /// process one base rate sample, 0 stages runs f once at the base rate
+    /// + x:        input
+    /// + stages:   number of 2x stages, up to MAX_STAGES
+    /// + f:        nonlinearity, called 2^stages times in order
+    pub fn process(&mut self, x: f64, stages: usize, mut f: impl FnMut(f64) -> f64) -> f64 {
+        let stages = stages.min(MAX_STAGES);
+        let mut buf = [0.0; 1 << MAX_STAGES];
+        buf[0] = x;
+
+        // zero-stuff and filter, doubling the gain to make up for the zeros
+        let mut n = 1;
+        for up in self.up.iter_mut().take(stages) {
+            let low = buf;
+            for (i, s) in low.iter().take(n).enumerate() {
+                buf[2 * i] = up.step(2.0 * s, self.economy);
+                buf[2 * i + 1] = up.step(0.0, self.economy);
+            }
+            n *= 2;
+        }
+
+        for s in buf.iter_mut().take(n) {
+            *s = f(*s);
+        }
+
+        // filter and keep the even samples, which line up with the base rate,
+        // walking back down the cascade
+        for down in self.down.iter_mut().take(stages).rev() {
+            n /= 2;
+            for i in 0..n {
+                let y = down.step(buf[2 * i], self.economy);
+                down.push(buf[2 * i + 1]);
+                buf[i] = y;
+            }
+        }
+        buf[0]
+    }
+
+    pub fn reset(&mut self) {
+        for f in self.up.iter_mut().chain(self.down.iter_mut()) {
+            f.reset();
+        }
+    }
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// latency of a cascade at the base rate in samples. Each stage delays by
+/// CENTER samples at its high rate on the way up and again on the way down.
+pub fn latency(stages: usize) -> usize {
+    (1..=stages.min(MAX_STAGES)).map(|k| (2 * CENTER) >> k).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// response of a cascade to an impulse, with the nonlinearity left out
+    fn impulse(stages: usize, economy: bool) -> Vec<f64> {
+        let mut os = Oversampler::new();
+        os.set_economy(economy);
+        (0..64).map(|n| os.process(if n == 0 { 1.0 } else { 0.0 }, stages, |x| x)).collect()
+    }
+
+    #[test]
+    fn both_kernels_peak_on_the_latency() {
+        for stages in 0..=MAX_STAGES {
+            for &economy in [false, true].iter() {
+                let y = impulse(stages, economy);
+                let at = (0..y.len()).max_by(|&a, &b| y[a].abs().partial_cmp(&y[b].abs()).unwrap()).unwrap();
+                assert_eq!(at, latency(stages), "{} stages, economy {}", stages, economy);
+                // unity at DC
+                assert!((y.iter().sum::<f64>() - 1.0).abs() < 1e-3);
+            }
+        }
+        assert_ne!(impulse(1, false), impulse(1, true));
+    }
+}
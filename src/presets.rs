@@ -0,0 +1,64 @@
+// Factory presets, each one a handful of parameter values on top of the
+// defaults, so presets don't need to change when parameters are added.
+
+/// a named factory preset
+pub struct Preset {
+    pub name: &'static str,
+    /// parameter index and value pairs, every other parameter is left at its
+    /// default
+    pub values: &'static [(i32, f32)],
+}
+
+/// the factory presets, in program order. The first one is the defaults.
+pub const FACTORY: [Preset; 6] = [
+    Preset { name: "Default", values: &[] },
+    Preset {
+        name: "Subtle Glue",
+        // +2 dB drive, 70% wet, bias trap, 15 ips head EQ
+        values: &[(0, 0.583), (3, 0.7), (11, 1.0), (46, 1.0), (47, 0.5)],
+    },
+    Preset {
+        name: "Crunchy 1/4 inch",
+        // +8 dB drive into steel, some thickness and even harmonics, 15 ips
+        // head EQ, 2x oversampling
+        values: &[(0, 0.833), (26, 0.3), (36, 0.5), (29, 0.3), (46, 1.0), (47, 0.5), (27, 0.375)],
+    },
+    Preset {
+        name: "Hot 30 ips",
+        // +6 dB drive into nickel, a little thickness, 30 ips head EQ, 4x
+        // oversampling
+        values: &[(0, 0.75), (26, 0.7), (36, 0.3), (46, 1.0), (47, 0.833), (27, 0.625)],
+    },
+    Preset {
+        name: "Worn Cassette",
+        // +4 dB drive into magnetite, 7.5 ips head EQ, wow, flutter and
+        // -60 dB hiss
+        values: &[(0, 0.667), (26, 0.9), (46, 1.0), (47, 0.167), (41, 0.4), (43, 0.3), (45, 0.5)],
+    },
+    Preset {
+        name: "Dusty Record",
+        // vinyl with crackle about once a second and some wow, loudness
+        // matched
+        values: &[(34, 1.0), (13, 0.25), (41, 0.2), (12, 1.0)],
+    },
+];
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_hold_valid_values_under_unique_names() {
+        assert!(FACTORY[0].values.is_empty());
+        for (i, p) in FACTORY.iter().enumerate() {
+            assert!(FACTORY[i + 1..].iter().all(|q| q.name != p.name), "{}", p.name);
+            for (j, &(index, value)) in p.values.iter().enumerate() {
+                assert!((0..crate::NUM_PARAMS).contains(&index), "{}", p.name);
+                assert!((0.0..=1.0).contains(&value), "{}", p.name);
+                // each parameter is set once
+                assert!(p.values[j + 1..].iter().all(|&(k, _)| k != index), "{}", p.name);
+            }
+        }
+    }
+}
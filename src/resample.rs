@@ -0,0 +1,168 @@
+// Sample-rate conversion of the nonlinear stage to a fixed internal rate, so
+// the saturation sounds the same whatever rate the host runs at.
+
+use std::f64::consts::PI;
+
+use crate::delay::DelayLine;
+
+/// rate the nonlinear stage runs at in fixed rate mode, in Hz
+pub const FIXED_RATE: f64 = 88200.0;
+
+// zero crossings on each side of the kernel, and table points per crossing
+const ZEROS: usize = 16;
+const PHASES: usize = 64;
+// passband edge as a fraction of the lower of the two Nyquist frequencies,
+// the transition band above it is where the kernel rolls off
+const PASSBAND: f64 = 0.9;
+
+/// Blackman windowed sinc, tabulated over its positive half in units of
+/// zero crossings
+fn table() -> Vec<f64> {
+    (0..ZEROS * PHASES + 2)
+        .map(|i| {
+            let u = i as f64 / PHASES as f64;
+            if u >= ZEROS as f64 {
+                return 0.0;
+            }
+            let sinc = if u == 0.0 { 1.0 } else { (PI * u).sin() / (PI * u) };
+            let w = PI * (1.0 + u / ZEROS as f64);
+            sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+        })
+        .collect()
+}
+
+/// band-limited interpolation of one stream at arbitrary fractional delays
+struct Interpolator {
+    table: Vec<f64>,
+    cutoff: f64,    // cutoff as a fraction of the input Nyquist
+    width: f64,     // half width of the kernel in input samples
+    line: DelayLine,
+}
+
+impl Interpolator {
+    /// + cutoff:       cutoff as a fraction of the input Nyquist, up to 1
+    /// + max_delay:    longest delay that will be read, in input samples
+    fn new(cutoff: f64, max_delay: f64) -> Self {
+        let width = ZEROS as f64 / cutoff;
+        Interpolator {
+            table: table(),
+            cutoff,
+            width,
+            line: DelayLine::new((max_delay + width).ceil() as usize + 1),
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        self.line.push(x);
+    }
+
+    /// kernel at a distance of d input samples
+    fn kernel(&self, d: f64) -> f64 {
+        let pos = (d.abs() * self.cutoff * PHASES as f64).min((ZEROS * PHASES) as f64);
+        let i = pos as usize;
+        let frac = pos - i as f64;
+        self.cutoff * (self.table[i] + frac * (self.table[i + 1] - self.table[i]))
+    }
+
+    /// the stream at a fractional delay, at least width samples back so the
+    /// whole kernel lies in the past
+    fn read(&self, delay: f64) -> f64 {
+        let first = (delay - self.width).ceil().max(0.0) as usize;
+        let last = (delay + self.width).floor() as usize;
+        (first..=last).map(|j| self.line.read(j) * self.kernel(j as f64 - delay)).sum()
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+/// runs a nonlinearity at FIXED_RATE, converting in and out of the host rate,
+/// one channel
+pub struct FixedRate {
+    up: Interpolator,   // host rate history
+    down: Interpolator, // internal rate history
+    step: f64,          // host samples per internal sample
+    lag: f64,           // how far back the next internal sample lies, in host samples
+    latency: usize,
+}
+
+impl FixedRate {
+    /// + rate:     host sample rate in Hz
+    pub fn new(rate: f64) -> Self {
+        let step = rate / FIXED_RATE;
+        // each side band-limits to the lower of the two rates
+        let up_cut = PASSBAND * (1.0 / step).min(1.0);
+        let down_cut = PASSBAND * step.min(1.0);
+        let up_width = ZEROS as f64 / up_cut;
+        let down_width = ZEROS as f64 / down_cut;
+        // an internal sample can only be made once the up kernel around it is
+        // in the past, and the output waits for the down kernel on top of that
+        let latency = (up_width + step * (down_width + 1.0)).ceil() as usize;
+        FixedRate {
+            up: Interpolator::new(up_cut, up_width + 1.0),
+            down: Interpolator::new(down_cut, latency as f64 / step + 1.0),
+            step,
+            lag: 0.0,
+            latency,
+        }
+    }
+
+    /// latency at the host rate in samples
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /// process one host rate sample
+    /// + x:    input
+    /// + f:    nonlinearity, called in order for every internal sample that
+    ///   falls within this host sample
+    pub fn process(&mut self, x: f64, mut f: impl FnMut(f64) -> f64) -> f64 {
+        self.up.push(x);
+        self.lag += 1.0;
+        while self.lag >= self.up.width {
+            let y = f(self.up.read(self.lag));
+            self.down.push(y);
+            self.lag -= self.step;
+        }
+        // the newest internal sample lies lag + step back, the output is read
+        // latency samples back at the host rate
+        self.down.read((self.latency as f64 - self.lag - self.step) / self.step)
+    }
+
+    pub fn reset(&mut self) {
+        self.up.reset();
+        self.down.reset();
+        self.lag = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_the_band_delayed_by_its_latency() {
+        for &rate in &[44100.0, 48000.0, 96000.0] {
+            let mut src = FixedRate::new(rate);
+            let mut calls = 0;
+            let x: Vec<f64> = (0..8192).map(|n| (2.0 * PI * 1000.0 * n as f64 / rate).sin()).collect();
+            let y: Vec<f64> = x
+                .iter()
+                .map(|&x| {
+                    src.process(x, |x| {
+                        calls += 1;
+                        x
+                    })
+                })
+                .collect();
+            let lat = src.latency();
+            let err = (4096..8192).fold(0.0, |p: f64, n| p.max((y[n] - x[n - lat]).abs()));
+            assert!(err < 1e-3, "{} {}", rate, err);
+            // the nonlinearity runs at the fixed rate, once the kernel in
+            // front of the first internal sample has filled
+            let expected = 8192.0 * FIXED_RATE / rate;
+            assert!((calls as f64 - expected).abs() < 64.0, "{} {}", rate, calls);
+        }
+    }
+}
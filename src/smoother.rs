@@ -0,0 +1,225 @@
+// Parameter smoothing, to keep automation free of zipper noise.
+
+use crate::envelope::time_coeff;
+
+/// shape of a parameter glide
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glide {
+    /// one-pole, fast at first and slowing down towards the target
+    Exponential,
+    /// constant speed, reaches the target after the glide time. Used for
+    /// parameters smoothed in dB, so gains glide linearly in dB.
+    Linear,
+}
+
+/// parameter smoother, snaps to the first target it is given
+pub struct Smoother {
+    glide: Glide,
+    coeff: f64,     // one-pole coefficient, exponential glides
+    len: f64,       // glide length in samples, linear glides
+    step: f64,      // per sample increment of the current linear glide
+    y: f64,
+    target: f64,
+    init: bool,     // whether the first target has been set
+}
+
+impl Smoother {
+    /// + time:     time constant (exponential) or glide time (linear), in
+    ///   seconds
+    /// + rate:     intersample period
+    /// + glide:    shape of the glide
+    pub fn new(time: f64, rate: f64, glide: Glide) -> Self {
+        let mut s = Smoother {
+            glide,
+            coeff: 1.0,
+            len: 1.0,
+            step: 0.0,
+            y: 0.0,
+            target: 0.0,
+            init: false,
+        };
+        s.set_time(time, rate);
+        s
+    }
+
+    /// + time:     time constant (exponential) or glide time (linear), in
+    ///   seconds
+    /// + rate:     intersample period
+    pub fn set_time(&mut self, time: f64, rate: f64) {
+        self.coeff = time_coeff(time, rate);
+        self.len = (time / rate).max(1.0);
+    }
+
+    pub fn set_target(&mut self, target: f64) {
+        if !self.init {
+            self.y = target;
+            self.init = true;
+        }
+        if target != self.target {
+            self.step = (target - self.y) / self.len;
+        }
+        self.target = target;
+    }
+
+    /// advance by one sample, returns the smoothed value
+    pub fn next(&mut self) -> f64 {
+        match self.glide {
+            Glide::Exponential => self.y += self.coeff * (self.target - self.y),
+            Glide::Linear => {
+                let remaining = self.target - self.y;
+                self.y = if remaining.abs() <= self.step.abs() {
+                    self.target
+                } else {
+                    self.y + self.step
+                };
+            }
+        }
+        self.y
+    }
+
+    /// forget the current value, the next target is snapped to
+    pub fn reset(&mut self) {
+        self.init = false;
+    }
+}
+
+/// click-free stage bypass, ramps linearly between passthrough and the
+/// processed signal when a stage is switched, fully off is an exact
+/// passthrough
+pub struct Bypass {
+    fade: Smoother,
+}
+
+impl Bypass {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        Bypass { fade: Smoother::new(BYPASS_TIME, rate, Glide::Linear) }
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.fade.set_time(BYPASS_TIME, rate);
+    }
+
+    /// advance by one sample, returns how much of the processed signal to
+    /// use, 0 - 1
+    /// + on:   whether the stage is engaged
+    pub fn step(&mut self, on: bool) -> f64 {
+        self.fade.set_target(if on { 1.0 } else { 0.0 });
+        self.fade.next()
+    }
+
+    /// forget the ramp, the next state is snapped to
+    pub fn reset(&mut self) {
+        self.fade.reset();
+    }
+}
+
+// duration of a bypass ramp in seconds
+const BYPASS_TIME: f64 = 0.005;
+
+/// fade-in after a stage had to be reset, so it comes back without a pop
+pub struct Recovery {
+    remaining: usize,   // samples left in the fade, 0 when not recovering
+    len: usize,         // length of the fade in samples
+}
+
+impl Recovery {
+    /// + rate:     intersample period
+    pub fn new(rate: f64) -> Self {
+        Recovery { remaining: 0, len: recovery_len(rate) }
+    }
+
+    /// + rate:     intersample period
+    pub fn set_rate(&mut self, rate: f64) {
+        self.len = recovery_len(rate);
+        self.remaining = self.remaining.min(self.len);
+    }
+
+    /// start a fade-in from silence
+    pub fn trigger(&mut self) {
+        self.remaining = self.len;
+    }
+
+    /// advance by one sample, returns the input faded in
+    pub fn step(&mut self, x: f64) -> f64 {
+        if self.remaining == 0 {
+            return x;
+        }
+        self.remaining -= 1;
+        x * (1.0 - self.remaining as f64 / self.len as f64)
+    }
+
+    pub fn reset(&mut self) {
+        self.remaining = 0;
+    }
+}
+
+// duration of a recovery fade-in in seconds
+const RECOVERY_TIME: f64 = 0.01;
+
+fn recovery_len(rate: f64) -> usize {
+    ((RECOVERY_TIME / rate).round() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 1.0 / 1000.0;
+
+    #[test]
+    fn linear_glides_at_constant_speed() {
+        // 10 ms at 1 kHz, 10 steps
+        let mut s = Smoother::new(0.01, RATE, Glide::Linear);
+        s.set_target(-12.0);
+        assert_eq!(s.next(), -12.0);
+        s.set_target(12.0);
+        for k in 1..=10 {
+            assert!((s.next() - (-12.0 + 2.4 * k as f64)).abs() < 1e-9);
+        }
+        assert_eq!(s.next(), 12.0);
+    }
+
+    #[test]
+    fn exponential_glides_slow_down() {
+        let mut s = Smoother::new(0.01, RATE, Glide::Exponential);
+        s.set_target(0.0);
+        s.set_target(1.0);
+        let y: Vec<f64> = (0..20).map(|_| s.next()).collect();
+        let a = time_coeff(0.01, RATE);
+        assert!((y[0] - a).abs() < 1e-12);
+        assert!(y.windows(3).all(|w| w[2] - w[1] < w[1] - w[0]));
+        assert!(y[19] < 1.0);
+    }
+
+    #[test]
+    fn bypass_ramps_and_rests_exactly() {
+        let mut b = Bypass::new(RATE);
+        assert_eq!(b.step(false), 0.0);
+        let ramp: Vec<f64> = (0..5).map(|_| b.step(true)).collect();
+        assert!((ramp[0] - 0.2).abs() < 1e-12);
+        assert!(ramp.windows(2).all(|w| (w[1] - w[0] - 0.2).abs() < 1e-9));
+        assert_eq!(b.step(true), 1.0);
+        for _ in 0..5 {
+            b.step(false);
+        }
+        assert_eq!(b.step(false), 0.0);
+        b.reset();
+        assert_eq!(b.step(true), 1.0);
+    }
+
+    #[test]
+    fn recovery_fades_in_over_its_length() {
+        let mut r = Recovery::new(RATE);
+        assert_eq!(r.step(1.0), 1.0);
+        r.trigger();
+        let len = recovery_len(RATE);
+        assert_eq!(len, 10);
+        let y: Vec<f64> = (0..len + 2).map(|_| r.step(1.0)).collect();
+        for (n, y) in y.iter().take(len).enumerate() {
+            assert!((y - (n + 1) as f64 / len as f64).abs() < 1e-12);
+        }
+        assert_eq!(&y[len..], &[1.0, 1.0]);
+    }
+}